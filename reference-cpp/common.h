@@ -9,6 +9,14 @@ uint randomize(uint64_t seed) {
   return (uint) seed;
 }
 
+// The input of the sort benchmarks consists of full 64-bit values, as generated by cases::sort::create_input.
+uint64_t randomize_64(uint64_t seed) {
+  seed ^= seed << 13;
+  seed ^= seed >> 7;
+  seed ^= seed << 17;
+  return seed;
+}
+
 bool predicate(uint64_t mask, uint64_t value) {
   value ^= value >> 11;
   value ^= value << 7;
@@ -22,6 +30,12 @@ void fill(int size, uint64_t* values) {
   }
 }
 
+void fill_sort_input(int size, uint64_t* values) {
+  for (int i = 0; i < size; i++) {
+    values[i] = randomize_64(i);
+  }
+}
+
 template<class P, class F>
 void run(P prepare, F f) {
   // Warm-up run
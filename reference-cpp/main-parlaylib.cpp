@@ -57,6 +57,17 @@ int main(int argc, char *argv[]) {
       [&] () { test_parallel_compact(mask, input, output); }
     );
 
+  } else if (std::strcmp(argv[1], "sort-parlay") == 0) {
+    parlay::sequence<uint64_t> sort_input = parlay::tabulate(size, [&] (ulong i) {
+      return randomize_64((uint64_t) i);
+    });
+    parlay::sequence<uint64_t> values = sort_input;
+
+    run(
+      [&] () { values = sort_input; },
+      [&] () { parlay::sort_inplace(values); }
+    );
+
   } else {
     printf("Unknown test case.\n");
   }
@@ -163,6 +163,13 @@ int main(int argc, char *argv[]) {
     }
     printf("%f\n", value / RUNS);
 
+  } else if (std::strcmp(argv[1], "sort-tbb") == 0) {
+    fill_sort_input(size, input);
+    run(
+      [&] () { std::copy(input, input + size, output); },
+      [&] () { oneapi::tbb::parallel_sort(output, output + size); }
+    );
+
   } else {
     printf("Unknown test case.\n");
   }
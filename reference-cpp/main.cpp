@@ -1,3 +1,4 @@
+#include <algorithm>
 #include <cstring>
 #include <string>
 #include "common.h"
@@ -61,6 +62,13 @@ int main(int argc, char *argv[]) {
       [&] () { test_sequential_compact(mask, size, input, output); }
     );
 
+  } else if (std::strcmp(argv[1], "sort-sequential") == 0) {
+    fill_sort_input(size, input);
+    run(
+      [&] () { std::copy(input, input + size, output); },
+      [&] () { std::sort(output, output + size); }
+    );
+
   } else {
     printf("Unknown test case.\n");
   }
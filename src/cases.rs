@@ -1,3 +1,10 @@
 pub mod compact;
+pub mod dedup;
+pub mod histogram;
+pub mod partition;
+pub mod rle;
+pub mod pipeline;
 pub mod scan;
+pub mod sort;
+pub mod split;
 pub mod scan_ratio;
@@ -1,18 +1,26 @@
+use core::cell::RefCell;
 use core::sync::atomic::{Ordering, AtomicU64};
 use std::sync::atomic::AtomicUsize;
 use num_format::{Locale, ToFormattedString};
 use crate::core::worker::*;
 use crate::utils;
-use crate::utils::benchmark::{benchmark_with_max_speedup, ChartStyle};
+use crate::utils::benchmark::{benchmark, benchmark_with_max_speedup, ChartStyle};
 use crate::utils::global_constants::{ COMP_MAX_SPEEDUP, COMP_MAX_THREADS};
+use crate::utils::shared_slice::SharedSlice;
+use crate::utils::stores::Stores;
 
 mod unchanged_half_sized;
-mod chained;
-mod our_chained;
+pub mod chained;
+pub mod our_chained;
 mod half_sized_blocks;
 mod our_half_sized_blocks;
+pub mod our_chained_by;
+pub mod our_chained_indices;
+pub mod our_chained_inplace;
+pub mod our_half_sized_blocks_by;
 mod half_sized_variant;
 mod no_lookback_chained;
+mod simd;
 
 pub const SIZE: usize = 1024 * 1024 * 256;
 pub const BLOCK_COUNT: u64 = 32 * 8;
@@ -81,6 +89,12 @@ pub fn run(cpp_enabled: bool) {
           Workers::run(thread_count, task);
           compute_output(&output, output_count.load(Ordering::Relaxed))
         })
+        .parallel("Adaptive chained scan (streaming stores)", 10, None, false, || {}, |thread_count| {
+          let output_count = AtomicUsize::new(0);
+          let task = our_chained::create_task_with_stores(mask, &input, &temp, &output, &output_count, Stores::for_output(&input, &output));
+          Workers::run(thread_count, task);
+          compute_output(&output, output_count.load(Ordering::Relaxed))
+        })
         .cpp_sequential(cpp_enabled, "Reference C++", &("compact-".to_owned() + &ratio.to_string() + "-sequential"), size)
         .cpp_tbb(cpp_enabled, "oneTBB", 1, None, &("compact-".to_owned() + &ratio.to_string() + "-tbb"), size)
         .cpp_parlay(cpp_enabled, "ParlayLib", 2, None, &("compact-".to_owned() + &ratio.to_string() + "-parlay"), size);
@@ -88,26 +102,232 @@ pub fn run(cpp_enabled: bool) {
   }
 }
 
+// Sweeps the selectivity of the predicate from 0% to 100%, with uniformly distributed and clustered selected elements.
+pub fn run_selectivity() {
+  let size = SIZE / 8;
+  let temp = chained::create_temp(size);
+  let half_sized_temp = unchanged_half_sized::create_temp(size);
+  let output = unsafe { utils::array::alloc_undef_u64_array(size) };
+
+  for distribution in [Distribution::Uniform, Distribution::Clustered] {
+    for percentage in [0, 1, 10, 25, 50, 75, 90, 99, 100] {
+      let input = create_input_with_selectivity(size, percentage as f64 / 100.0, distribution);
+      let mask = SELECTIVITY_MASK;
+      let name = "Compact (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", p = " + &percentage.to_string() + "%, " + distribution.name() + ")";
+      benchmark_with_max_speedup(
+          if percentage == 0 { ChartStyle::WithKey } else { ChartStyle::WithoutKey },
+          &name,
+          || {},
+          || reference_sequential_single(mask, &input, &output),
+          COMP_MAX_THREADS,
+          COMP_MAX_SPEEDUP
+        )
+        .parallel("Chained scan", 4, None, false, || {}, |thread_count| {
+          let output_count = AtomicUsize::new(0);
+          let task = chained::create_task(mask, &input, &temp, &output, &output_count);
+          Workers::run(thread_count, task);
+          compute_output(&output, output_count.load(Ordering::Relaxed))
+        })
+        .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+          let output_count = AtomicUsize::new(0);
+          let task = our_chained::create_task(mask, &input, &temp, &output, &output_count);
+          Workers::run(thread_count, task);
+          compute_output(&output, output_count.load(Ordering::Relaxed))
+        })
+        .parallel("Our Half-sized blocks", 8, None, true, || {}, |thread_count| {
+          let output_count = AtomicUsize::new(0);
+          let task = our_half_sized_blocks::create_task(mask, &input, &half_sized_temp, &output, &output_count);
+          Workers::run(thread_count, task);
+          compute_output(&output, output_count.load(Ordering::Relaxed))
+        });
+    }
+  }
+}
+
+// Compacts the array in place, overwriting the input with the kept elements.
+pub fn run_inplace() {
+  let size = SIZE / 8;
+  for ratio in [2, 8] {
+    let input = create_input(size);
+    let temp = chained::create_temp(size);
+    let values = unsafe { utils::array::alloc_undef_u64_array(size) };
+    let mask = ratio - 1;
+    let filter = |value: &u64| predicate(mask, *value);
+
+    let name = "Compact inplace (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", r = 1/" + &ratio.to_string() + ")";
+    benchmark(
+        ChartStyle::WithKey,
+        &name,
+        || { fill(&values, &input) },
+        || {
+          let count = compact_inplace_sequential(&filter, &values, 0, size, 0);
+          compute_output(&values, count)
+        }
+      )
+      .parallel("Adaptive chained scan", 7, None, true, || { fill(&values, &input) }, |thread_count| {
+        let output_count = AtomicUsize::new(0);
+        let task = our_chained_inplace::create_task(&filter, &values, &temp, &output_count);
+        Workers::run(thread_count, task);
+        compute_output(&values, output_count.load(Ordering::Relaxed))
+      });
+  }
+}
+
+pub fn fill(values: &[AtomicU64], input: &[u64]) {
+  for (value, &x) in values.iter().zip(input) {
+    value.store(x, Ordering::Relaxed);
+  }
+}
+
+// Selects the indices of the elements that satisfy the predicate, to build a sparse index list from a dense mask.
+pub fn run_indices() {
+  let size = SIZE / 8;
+  let ratio = 8;
+  let input = create_input(size);
+  let temp = chained::create_temp(size);
+  let output = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let flags = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let mask = ratio - 1;
+  let filter = |value: &u64| predicate(mask, *value);
+
+  let name = "Select indices (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", r = 1/" + &ratio.to_string() + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        let count = select_indices_sequential_by(&filter, &input, 0, &output, 0, None);
+        compute_output(&output, count)
+      }
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let output_count = AtomicUsize::new(0);
+      let task = our_chained_indices::create_task(&filter, &input, &temp, &output, None, &output_count);
+      Workers::run(thread_count, task);
+      compute_output(&output, output_count.load(Ordering::Relaxed))
+    })
+    .parallel("Adaptive chained scan (flags)", 8, None, true, || {}, |thread_count| {
+      let output_count = AtomicUsize::new(0);
+      let task = our_chained_indices::create_task(&filter, &input, &temp, &output, Some(&flags), &output_count);
+      Workers::run(thread_count, task);
+      compute_output(&output, output_count.load(Ordering::Relaxed))
+    });
+}
+
+// A record with a key and a payload, to benchmark compact with a predicate closure on a non-integer element type.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct Record {
+  pub key: u64,
+  pub value: u64
+}
+
+pub fn run_records() {
+  let size = SIZE / 8;
+  let ratio = 2;
+  let input = create_records(size);
+  let temp = chained::create_temp(size);
+  let half_sized_temp = unchanged_half_sized::create_temp(size);
+  let output = RefCell::new(vec![Record::default(); size].into_boxed_slice());
+  let mask = ratio - 1;
+  let filter = |record: &Record| predicate(mask, record.key);
+
+  let name = "Compact records (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", r = 1/" + &ratio.to_string() + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        let mut output = output.borrow_mut();
+        let count = compact_sequential_by(&filter, &input, &SharedSlice::new(&mut output), 0);
+        compute_output_by(&output, count)
+      }
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let mut output = output.borrow_mut();
+      let output_count = AtomicUsize::new(0);
+      let task = our_chained_by::create_task(&filter, &input, &temp, SharedSlice::new(&mut output), &output_count);
+      Workers::run(thread_count, task);
+      compute_output_by(&output, output_count.load(Ordering::Relaxed))
+    })
+    .parallel("Our Half-sized blocks", 8, None, true, || {}, |thread_count| {
+      let mut output = output.borrow_mut();
+      let output_count = AtomicUsize::new(0);
+      let task = our_half_sized_blocks_by::create_task(&filter, &input, &half_sized_temp, SharedSlice::new(&mut output), &output_count);
+      Workers::run(thread_count, task);
+      compute_output_by(&output, output_count.load(Ordering::Relaxed))
+    });
+}
+
+pub fn create_records(size: usize) -> Box<[Record]> {
+  (0..size).map(|x| Record{ key: random(x as u64) as u64, value: x as u64 }).collect()
+}
+
+pub fn compute_output_by(output: &[Record], count: usize) -> (usize, Record, Record, Record) {
+  (count, output[0], output[98238], output[count - 1])
+}
+
 pub fn create_input(size: usize) -> Box<[u64]> {
   (0..size).map(|x| random(x as u64) as u64).collect()
 }
 
+// The mask to use with the inputs of create_input_with_selectivity.
+pub const SELECTIVITY_MASK: u64 = 1;
+// The number of consecutive elements that are all selected or all not selected in Distribution::Clustered.
+const CLUSTER_SIZE: usize = 1024 * 64;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Distribution {
+  // Each element is selected independently.
+  Uniform,
+  // The elements are selected in clusters, such that some blocks contain only selected elements and others none.
+  Clustered
+}
+
+impl Distribution {
+  pub fn name(self) -> &'static str {
+    match self {
+      Distribution::Uniform => "uniform",
+      Distribution::Clustered => "clustered"
+    }
+  }
+}
+
+// Creates an input where a fraction 'probability' of the elements satisfies the predicate with SELECTIVITY_MASK.
+// Flipping the lowest bit of a value flips the lowest bit of the hash in 'predicate',
+// hence we can select any element by choosing that bit.
+pub fn create_input_with_selectivity(size: usize, probability: f64, distribution: Distribution) -> Box<[u64]> {
+  (0..size).map(|x| {
+    let group = match distribution {
+      Distribution::Uniform => x,
+      Distribution::Clustered => x / CLUSTER_SIZE
+    };
+    // Spread the bits of the group index, as 'random' gives small numbers for small seeds.
+    let sample = random((group as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let selected = (sample as f64) < probability * (u32::MAX as f64 + 1.0);
+    let value = random(x as u64) as u64;
+    if predicate(SELECTIVITY_MASK, value) == selected { value } else { value ^ 1 }
+  }).collect()
+}
+
 pub fn compute_output(output: &[AtomicU64], count: usize) -> (usize, u64) {
+  if count == 0 {
+    return (0, 0);
+  }
   (
     count,
-    output[0].load(Ordering::Relaxed) + output[98238].load(Ordering::Relaxed) + output[count - 1].load(Ordering::Relaxed)
+    output[0].load(Ordering::Relaxed) + output[98238.min(count - 1)].load(Ordering::Relaxed) + output[count - 1].load(Ordering::Relaxed)
   )
 }
 
-pub fn compact_sequential(mask: u64, input: &[u64], output: &[AtomicU64], mut output_index: usize) -> usize {
-  for &value in input {
-    if predicate(mask, value) {
-      unsafe { output.get_unchecked(output_index) }.store(value, Ordering::Relaxed);
-      output_index += 1;
-    }
-  }
+pub fn compact_sequential(mask: u64, input: &[u64], output: &[AtomicU64], output_index: usize) -> usize {
+  simd::compact(mask, input, output, output_index)
+}
 
-  output_index
+pub fn compact_sequential_with_stores(mask: u64, input: &[u64], output: &[AtomicU64], output_index: usize, stores: Stores) -> usize {
+  match stores {
+    Stores::Regular => simd::compact(mask, input, output, output_index),
+    Stores::Streaming => simd::compact_streaming(mask, input, output, output_index)
+  }
 }
 
 pub fn reference_sequential_single(mask: u64, input: &[u64], output: &[AtomicU64]) -> (usize, u64) {
@@ -116,25 +336,114 @@ pub fn reference_sequential_single(mask: u64, input: &[u64], output: &[AtomicU64
 }
 
 pub fn count_sequential(mask: u64, input: &[u64]) -> usize {
-  let mut count = 0;
-  for &value in input {
-    if predicate(mask, value) {
-      count += 1;
+  simd::count(mask, input)
+}
+
+// The sequential kernels of compact for one block: counting the elements that satisfy the predicate,
+// and writing them to the output. The adaptive variants our_chained_by and our_half_sized_blocks_by are generic over this,
+// such that the variants with a mask use the SIMD kernels and the variants with a predicate closure accept any element type.
+pub trait CompactKernel<T>: Send + Sync {
+  fn count(&self, input: &[T]) -> usize;
+  // Returns the index after the last written element.
+  fn compact(&self, input: &[T], output_index: usize) -> usize;
+}
+
+// Compact with a predicate closure, to an output of any element type.
+pub struct PredicateKernel<'a, T, P> {
+  pub predicate: &'a P,
+  pub output: SharedSlice<'a, T>
+}
+
+impl<T: Clone + Send + Sync, P: Fn(&T) -> bool + Sync> CompactKernel<T> for PredicateKernel<'_, T, P> {
+  fn count(&self, input: &[T]) -> usize {
+    count_sequential_by(self.predicate, input)
+  }
+  fn compact(&self, input: &[T], output_index: usize) -> usize {
+    compact_sequential_by(self.predicate, input, &self.output, output_index)
+  }
+}
+
+// Compact of u64 values with the predicate |value| predicate(mask, *value), using the SIMD kernels.
+pub struct MaskKernel<'a> {
+  pub mask: u64,
+  pub output: &'a [AtomicU64],
+  pub stores: Stores
+}
+
+impl CompactKernel<u64> for MaskKernel<'_> {
+  fn count(&self, input: &[u64]) -> usize {
+    count_sequential(self.mask, input)
+  }
+  fn compact(&self, input: &[u64], output_index: usize) -> usize {
+    compact_sequential_with_stores(self.mask, input, self.output, output_index, self.stores)
+  }
+}
+
+// Writes the elements of the input that satisfy the predicate to the output, starting at output_index.
+// The caller must assure that no other thread writes to that part of the output concurrently.
+// Returns the index after the last written element.
+pub fn compact_sequential_by<T: Clone, P: Fn(&T) -> bool>(predicate: &P, input: &[T], output: &SharedSlice<T>, output_index: usize) -> usize {
+  let mut index = output_index;
+  for value in input {
+    if predicate(value) {
+      unsafe { output.write(index, value.clone()) };
+      index += 1;
     }
   }
-  count
+  index
 }
 
-pub fn scan_indices_sequential(mask: u64, input: &[u64], output: &[AtomicU64]) -> usize {
-  assert_eq!(input.len(), output.len());
-  let mut count = 0;
-  for i in 0 .. input.len() {
-    if predicate(mask, input[i]) {
-      count += 1;
+// Moves the elements in values[start .. end] that satisfy the predicate to the front, starting at output_index.
+// output_index should be at most start. The caller must assure that no other thread accesses
+// values[output_index .. end] concurrently.
+// Returns the index after the last written element.
+pub fn compact_inplace_sequential<P: Fn(&u64) -> bool>(predicate: &P, values: &[AtomicU64], start: usize, end: usize, output_index: usize) -> usize {
+  assert!(output_index <= start);
+  let mut index = output_index;
+  for i in start .. end {
+    let value = values[i].load(Ordering::Relaxed);
+    if predicate(&value) {
+      values[index].store(value, Ordering::Relaxed);
+      index += 1;
+    }
+  }
+  index
+}
+
+// Writes the indices of the elements that satisfy the predicate to the output, starting at output_index.
+// input_offset is the index of the first element of input in the whole array.
+// If flags is given, it is filled with the inclusive prefix-sum of the predicate, that is the output index after each element.
+// Returns the index after the last written index.
+pub fn select_indices_sequential_by<T, P: Fn(&T) -> bool>(predicate: &P, input: &[T], input_offset: usize, output: &[AtomicU64], output_index: usize, flags: Option<&[AtomicU64]>) -> usize {
+  if let Some(flags) = flags {
+    return scan_indices_sequential_by(predicate, input, input_offset, output, output_index, flags);
+  }
+  let mut index = output_index;
+  for (i, value) in input.iter().enumerate() {
+    if predicate(value) {
+      output[index].store((input_offset + i) as u64, Ordering::Relaxed);
+      index += 1;
     }
-    output[i].store(count as u64, Ordering::Relaxed);
   }
-  count
+  index
+}
+
+pub fn count_sequential_by<T, P: Fn(&T) -> bool>(predicate: &P, input: &[T]) -> usize {
+  input.iter().filter(|value| predicate(value)).count()
+}
+
+// As select_indices_sequential_by, and fills flags with the inclusive prefix-sum of the predicate, starting at output_index.
+pub fn scan_indices_sequential_by<T, P: Fn(&T) -> bool>(predicate: &P, input: &[T], input_offset: usize, output: &[AtomicU64], output_index: usize, flags: &[AtomicU64]) -> usize {
+  assert_eq!(input.len(), flags.len());
+  let mut index = output_index;
+  for (i, value) in input.iter().enumerate() {
+    if predicate(value) {
+      output[index].store((input_offset + i) as u64, Ordering::Relaxed);
+      index += 1;
+    }
+    flags[i].store(index as u64, Ordering::Relaxed);
+  }
+  index
 }
 
 fn random(mut seed: u64) -> u32 {
@@ -150,3 +459,88 @@ pub fn predicate(mask: u64, mut value: u64) -> bool {
   value ^= value >> 5;
   (value & mask) == mask
 }
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+  use crate::cases::compact::*;
+  use crate::utils::testing::thread_counts;
+
+  // Compacts the input in place with the parallel implementation, for each thread count,
+  // and checks that this gives the kept elements in their original order.
+  fn inplace<P: Fn(&u64) -> bool + Sync>(input: &[u64], filter: P) {
+    let expected: Vec<u64> = input.iter().copied().filter(|value| filter(value)).collect();
+    let temp = chained::create_temp(input.len());
+    for thread_count in thread_counts() {
+      let values: Box<[AtomicU64]> = input.iter().map(|&value| AtomicU64::new(value)).collect();
+      let output_count = AtomicUsize::new(usize::MAX);
+      Workers::run(thread_count, our_chained_inplace::create_task(&filter, &values, &temp, &output_count));
+      let count = output_count.load(Ordering::Relaxed);
+      assert_eq!(count, expected.len());
+      let output: Vec<u64> = values[0 .. count].iter().map(|value| value.load(Ordering::Relaxed)).collect();
+      assert_eq!(output, expected);
+    }
+  }
+
+  const SIZES: [usize; 6] = [0, 1, chained::BLOCK_SIZE as usize - 1, chained::BLOCK_SIZE as usize, chained::BLOCK_SIZE as usize + 1, 100_003];
+
+  #[test]
+  fn inplace_all_kept() {
+    for size in SIZES {
+      inplace(&create_input(size), |_| true);
+    }
+  }
+
+  #[test]
+  fn inplace_all_dropped() {
+    for size in SIZES {
+      inplace(&create_input(size), |_| false);
+    }
+  }
+
+  #[test]
+  fn inplace_overlapping_destination() {
+    // With most elements kept, the destination of a block overlaps the range of the previous block.
+    for size in SIZES {
+      inplace(&create_input(size), |value| !predicate(7, *value));
+      inplace(&create_input(size), |value| predicate(1, *value));
+    }
+  }
+
+  // Selects the indices with the parallel implementation, with and without flags, for each thread count,
+  // and checks the indices against filter, and the flags against the inclusive prefix-sum of the predicate.
+  fn select_indices<P: Fn(&u64) -> bool + Sync>(input: &[u64], filter: P) {
+    let expected: Vec<u64> = (0 .. input.len() as u64).filter(|&i| filter(&input[i as usize])).collect();
+    let expected_flags: Vec<u64> = input.iter().scan(0, |count, value| {
+      *count += filter(value) as u64;
+      Some(*count)
+    }).collect();
+    let temp = chained::create_temp(input.len());
+    for thread_count in thread_counts() {
+      for with_flags in [false, true] {
+        let output: Box<[AtomicU64]> = input.iter().map(|_| AtomicU64::new(u64::MAX)).collect();
+        let flags: Box<[AtomicU64]> = input.iter().map(|_| AtomicU64::new(u64::MAX)).collect();
+        let output_count = AtomicUsize::new(usize::MAX);
+        let task = our_chained_indices::create_task(&filter, input, &temp, &output, if with_flags { Some(&flags) } else { None }, &output_count);
+        Workers::run(thread_count, task);
+        let count = output_count.load(Ordering::Relaxed);
+        assert_eq!(count, expected.len());
+        let indices: Vec<u64> = output[0 .. count].iter().map(|value| value.load(Ordering::Relaxed)).collect();
+        assert_eq!(indices, expected);
+        if with_flags {
+          let flags: Vec<u64> = flags.iter().map(|value| value.load(Ordering::Relaxed)).collect();
+          assert_eq!(flags, expected_flags);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn select_indices_sizes() {
+    for size in SIZES {
+      select_indices(&create_input(size), |value| predicate(1, *value));
+      select_indices(&create_input(size), |value| predicate(7, *value));
+      select_indices(&create_input(size), |_| false);
+    }
+  }
+}
@@ -3,6 +3,7 @@ use crate::cases::compact::{compact_sequential, count_sequential};
 use crate::core::worker::*;
 use crate::core::task::*;
 use crate::core::workassisting_loop::*;
+use crate::utils::lookback_wait::LookbackWait;
 
 pub const BLOCK_SIZE: u64 = 1024 * 4;
 
@@ -26,10 +27,222 @@ pub struct BlockInfo {
   pub prefix: AtomicUsize
 }
 
+// The descriptor of a block for a k-way split, with a counter per bucket instead of a single count.
+// See cases::split.
+pub struct HistogramBlockInfo<const K: usize> {
+  pub state: AtomicU64,
+  pub aggregate: [AtomicUsize; K],
+  pub prefix: [AtomicUsize; K]
+}
+
+pub fn create_histogram_temp<const K: usize>(size: usize) -> Box<[HistogramBlockInfo<K>]> {
+  (0 .. (size as u64).div_ceil(BLOCK_SIZE)).map(|_| HistogramBlockInfo{
+    state: AtomicU64::new(STATE_INITIALIZED),
+    aggregate: core::array::from_fn(|_| AtomicUsize::new(0)),
+    prefix: core::array::from_fn(|_| AtomicUsize::new(0))
+  }).collect()
+}
+
+// The counters are only read after the state says that they are written,
+// hence we only need to reset the states.
+pub fn reset_histogram<const K: usize>(temp: &[HistogramBlockInfo<K>]) {
+  for block in temp {
+    block.state.store(STATE_INITIALIZED, Ordering::Relaxed);
+  }
+}
+
 pub const STATE_INITIALIZED: u64 = 0;
 pub const STATE_AGGREGATE_AVAILABLE: u64 = 1;
 pub const STATE_PREFIX_AVAILABLE: u64 = 2;
 
+// A block descriptor of the adaptive chained scan, whose aggregate and prefix are values of a monoid.
+// This allows the variants of compact and split to share the lookback.
+pub trait ChainedBlock {
+  type Value: Copy;
+  fn zero() -> Self::Value;
+  fn combine(left: Self::Value, right: Self::Value) -> Self::Value;
+  fn state(&self) -> &AtomicU64;
+  fn load_aggregate(&self) -> Self::Value;
+  fn load_prefix(&self) -> Self::Value;
+  // The caller publishes the stored value by updating the state afterwards, with release ordering.
+  fn store_aggregate(&self, value: Self::Value);
+  fn store_prefix(&self, value: Self::Value);
+}
+
+impl ChainedBlock for BlockInfo {
+  type Value = usize;
+
+  fn zero() -> usize {
+    0
+  }
+  fn combine(left: usize, right: usize) -> usize {
+    left + right
+  }
+  fn state(&self) -> &AtomicU64 {
+    &self.state
+  }
+  fn load_aggregate(&self) -> usize {
+    self.aggregate.load(Ordering::Acquire)
+  }
+  fn load_prefix(&self) -> usize {
+    self.prefix.load(Ordering::Acquire)
+  }
+  fn store_aggregate(&self, value: usize) {
+    self.aggregate.store(value, Ordering::Relaxed);
+  }
+  fn store_prefix(&self, value: usize) {
+    self.prefix.store(value, Ordering::Relaxed);
+  }
+}
+
+impl<const K: usize> ChainedBlock for HistogramBlockInfo<K> {
+  type Value = [usize; K];
+
+  fn zero() -> [usize; K] {
+    [0; K]
+  }
+  fn combine(mut left: [usize; K], right: [usize; K]) -> [usize; K] {
+    for (value, other) in left.iter_mut().zip(right) {
+      *value += other;
+    }
+    left
+  }
+  fn state(&self) -> &AtomicU64 {
+    &self.state
+  }
+  fn load_aggregate(&self) -> [usize; K] {
+    core::array::from_fn(|bucket| self.aggregate[bucket].load(Ordering::Relaxed))
+  }
+  fn load_prefix(&self) -> [usize; K] {
+    core::array::from_fn(|bucket| self.prefix[bucket].load(Ordering::Relaxed))
+  }
+  fn store_aggregate(&self, value: [usize; K]) {
+    for (counter, count) in self.aggregate.iter().zip(value) {
+      counter.store(count, Ordering::Relaxed);
+    }
+  }
+  fn store_prefix(&self, value: [usize; K]) {
+    for (counter, count) in self.prefix.iter().zip(value) {
+      counter.store(count, Ordering::Relaxed);
+    }
+  }
+}
+
+// The lookback of the adaptive chained scan. Returns the exclusive prefix of block 'block_index',
+// by combining the aggregates of the previous blocks until it finds a block whose prefix is available.
+// A state above STATE_PREFIX_AVAILABLE is also treated as a published prefix, see compact::our_chained_inplace.
+// If the state of a previous block does not change within LOOKBACK_TIMEOUT, the thread working on that block
+// may be descheduled. Instead of waiting for it, we then compute the aggregate of that block ourselves with 'reduce'.
+// If the task is cancelled, this stops without finding the prefix, and returns an incorrect value.
+pub fn lookback<B: ChainedBlock, R: FnMut(u32) -> B::Value>(workers: &Workers, temp: &[B], block_index: u32, mut reduce: R) -> B::Value {
+  lookback_with(workers, temp, block_index, |previous| Some(reduce(previous)))
+}
+
+// As lookback, for scans that cannot reduce a previous block, for instance because that block may already be overwritten.
+// Instead, this yields to give the thread working on that block a chance to progress.
+pub fn lookback_yielding<B: ChainedBlock>(workers: &Workers, temp: &[B], block_index: u32) -> B::Value {
+  lookback_with(workers, temp, block_index, |_| None)
+}
+
+fn lookback_with<B: ChainedBlock, R: FnMut(u32) -> Option<B::Value>>(workers: &Workers, temp: &[B], block_index: u32, mut reduce: R) -> B::Value {
+  let mut aggregate = B::zero();
+  if block_index == 0 {
+    return aggregate;
+  }
+  let mut previous = block_index - 1;
+  let mut wait = LookbackWait::new();
+
+  loop {
+    let previous_block = &temp[previous as usize];
+    let previous_state = previous_block.state().load(Ordering::Acquire);
+    if previous_state >= STATE_PREFIX_AVAILABLE {
+      return B::combine(previous_block.load_prefix(), aggregate);
+    } else if previous_state == STATE_AGGREGATE_AVAILABLE {
+      aggregate = B::combine(previous_block.load_aggregate(), aggregate);
+      previous -= 1;
+      wait.reset();
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      return aggregate;
+    } else if !wait.expired() {
+      // Continue looping until the state of previous block changes.
+    } else if let Some(value) = reduce(previous) {
+      // The thread working on the previous block may be descheduled.
+      // Instead of waiting for it, we reduced that block ourselves.
+      aggregate = B::combine(value, aggregate);
+      if previous == 0 {
+        return aggregate;
+      }
+      previous -= 1;
+      wait.reset();
+    } else {
+      std::thread::yield_now();
+    }
+  }
+}
+
+pub fn block_count(length: usize) -> u32 {
+  (length as u64).div_ceil(BLOCK_SIZE) as u32
+}
+
+pub fn block_range(length: usize, block_index: u32) -> (usize, usize) {
+  let start = block_index as usize * BLOCK_SIZE as usize;
+  let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(length);
+  (start, end)
+}
+
+// The loop of the adaptive chained scan, shared by compact and the operations built on it.
+// 'count(block_index)' computes the aggregate of a block. This is also used to reduce a previous block in the lookback.
+// 'write(block_index, prefix)' writes the output of a block given its exclusive prefix, and returns its inclusive prefix.
+// While a thread finds the prefix of the previous block, it writes its blocks directly (sequential mode).
+// Otherwise it counts its block, shares that aggregate and performs the lookback, before it writes the block (parallel mode).
+pub fn adaptive_scan<B, C, W>(workers: &Workers, temp: &[B], loop_arguments: LoopArguments, count: C, write: W)
+where
+  B: ChainedBlock,
+  C: Fn(u32) -> B::Value,
+  W: Fn(u32, B::Value) -> B::Value
+{
+  let mut sequential = true;
+  workassisting_loop!(loop_arguments, |block_index| {
+    let block = &temp[block_index as usize];
+
+    // Check if we already have the prefix of the previous block.
+    // If that is the case, then we can write the output directly.
+    // Otherwise we perform a reduce-then-scan over this block.
+    let prefix_start = if !sequential {
+      None // Don't switch back from parallel mode to sequential mode
+    } else if block_index == 0 {
+      Some(B::zero())
+    } else {
+      let previous = &temp[block_index as usize - 1];
+      if previous.state().load(Ordering::Acquire) == STATE_PREFIX_AVAILABLE {
+        Some(previous.load_prefix())
+      } else {
+        None
+      }
+    };
+
+    if let Some(prefix) = prefix_start {
+      let local = write(block_index, prefix);
+      block.store_prefix(local);
+      block.state().store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+    } else {
+      sequential = false;
+      let local = count(block_index);
+      // Share own local value
+      block.store_aggregate(local);
+      block.state().store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
+
+      let aggregate = lookback(workers, temp, block_index, &count);
+
+      // Make prefix available
+      block.store_prefix(B::combine(aggregate, local));
+      block.state().store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+      write(block_index, aggregate);
+    }
+  });
+}
+
 #[derive(Copy, Clone)]
 pub struct Data<'a> {
   pub mask: u64,
@@ -44,7 +257,7 @@ pub fn create_task(mask: u64, input: &[u64], temp: &[BlockInfo], output: &[Atomi
   Task::new_dataparallel::<Data>(run, finish, Data{ mask, input, temp, output, output_count }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   workassisting_loop!(loop_arguments, |block_index| {
     // Local scan
@@ -62,22 +275,11 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
       data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
       data.temp[block_index as usize].state.store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
 
-      // Find aggregate
-      let mut aggregate = 0;
-      let mut previous = block_index - 1;
-
-      loop {
-        let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
-        if previous_state == STATE_PREFIX_AVAILABLE {
-          aggregate = data.temp[previous as usize].prefix.load(Ordering::Acquire) + aggregate;
-          break;
-        } else if previous_state == STATE_AGGREGATE_AVAILABLE {
-          aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
-          previous = previous - 1;
-        } else {
-          // Continue looping until the state of previous block changes.
-        }
-      }
+      let aggregate = lookback(workers, data.temp, block_index, |previous| {
+        let previous_start = previous as usize * BLOCK_SIZE as usize;
+        let previous_end = previous_start + BLOCK_SIZE as usize;
+        count_sequential(data.mask, &data.input[previous_start .. previous_end])
+      });
 
       // Make aggregate available
       data.temp[block_index as usize].prefix.store(aggregate + local, Ordering::Relaxed);
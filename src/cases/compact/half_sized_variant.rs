@@ -16,7 +16,7 @@ pub fn create_task(mask: u64, input: &[u64], temp: &[BlockInfo], output: &[Atomi
   Task::new_dataparallel::<Data>(run, finish, Data{ mask, input, temp, output, output_count }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   
     // Update this after every loop
@@ -70,7 +70,7 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
         // Need to make room for new unfinished block
 
         if let Some(u_index) = unfinished_index {
-          process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+          process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
         }
 
         // Replace unfinished block with current block
@@ -84,12 +84,12 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
 
   // perform last unfinished block
   if let Some(u_index) = unfinished_index {
-    process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+    process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
   }
 }
 
 #[inline(always)]
-fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: usize) {
+fn process_unfinished_block(workers: &Workers, data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: usize) {
   // Find aggregate
   let mut aggregate = 0;
   let mut previous = u_index - 1;
@@ -102,6 +102,9 @@ fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize,
     } else if previous_state == STATE_AGGREGATE_AVAILABLE {
       aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
       previous = previous - 1;
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      break;
     } else {
       // Continue looping until the state of previous block changes.
     }
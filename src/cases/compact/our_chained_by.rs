@@ -0,0 +1,45 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::compact::{CompactKernel, PredicateKernel};
+use crate::cases::compact::chained::{ BlockInfo, adaptive_scan, block_count, block_range, reset };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::utils::shared_slice::SharedSlice;
+
+// Adaptive chained scan for compact, with an arbitrary element type and predicate.
+pub fn create_task<T: Clone + Send + Sync, P: Fn(&T) -> bool + Sync>(predicate: &P, input: &[T], temp: &[BlockInfo], output: SharedSlice<T>, output_count: &AtomicUsize) -> Task {
+  create_task_with_kernel(PredicateKernel{ predicate, output }, input, temp, output_count)
+}
+
+// As create_task, with the kernels that count and write the selected elements of a block. See CompactKernel.
+pub fn create_task_with_kernel<T: Sync, K: CompactKernel<T>>(kernel: K, input: &[T], temp: &[BlockInfo], output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  Task::new_dataparallel::<Data<T, K>>(run::<T, K>, finish::<T, K>, Data{ kernel, input, temp, output_count }, block_count(input.len()), false)
+}
+
+struct Data<'a, T, K> {
+  kernel: K,
+  input: &'a [T],
+  temp: &'a [BlockInfo],
+  output_count: &'a AtomicUsize
+}
+
+fn run<T, K: CompactKernel<T>>(workers: &Workers, task: *const TaskObject<Data<T, K>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      data.kernel.count(&data.input[start .. end])
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      data.kernel.compact(&data.input[start .. end], prefix)
+    }
+  );
+}
+
+fn finish<T, K>(workers: &Workers, task: *mut TaskObject<Data<T, K>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
@@ -0,0 +1,46 @@
+use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+use crate::cases::compact::{count_sequential_by, select_indices_sequential_by};
+use crate::cases::compact::chained::{ BlockInfo, adaptive_scan, block_count, block_range, reset };
+use crate::core::worker::*;
+use crate::core::task::*;
+
+// Adaptive chained scan, like our_chained_by, which writes the indices of the elements that satisfy the predicate
+// instead of the elements themselves.
+// If flags is given, it is filled with the inclusive prefix-sum of the predicate.
+pub fn create_task<T: Sync, P: Fn(&T) -> bool + Sync>(predicate: &P, input: &[T], temp: &[BlockInfo], output: &[AtomicU64], flags: Option<&[AtomicU64]>, output_count: &AtomicUsize) -> Task {
+  if let Some(flags) = flags {
+    assert_eq!(input.len(), flags.len());
+  }
+  reset(temp);
+  Task::new_dataparallel::<Data<T, P>>(run::<T, P>, finish::<T, P>, Data{ predicate, input, temp, output, flags, output_count }, block_count(input.len()), false)
+}
+
+struct Data<'a, T, P> {
+  predicate: &'a P,
+  input: &'a [T],
+  temp: &'a [BlockInfo],
+  output: &'a [AtomicU64],
+  flags: Option<&'a [AtomicU64]>,
+  output_count: &'a AtomicUsize
+}
+
+fn run<T, P: Fn(&T) -> bool>(workers: &Workers, task: *const TaskObject<Data<T, P>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      count_sequential_by(data.predicate, &data.input[start .. end])
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      select_indices_sequential_by(data.predicate, &data.input[start .. end], start, data.output, prefix, data.flags.map(|flags| &flags[start .. end]))
+    }
+  );
+}
+
+fn finish<T, P>(workers: &Workers, task: *mut TaskObject<Data<T, P>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
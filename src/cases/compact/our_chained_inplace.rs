@@ -0,0 +1,104 @@
+use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+use crate::cases::compact::{compact_inplace_sequential, count_sequential_by};
+use crate::cases::compact::chained::{ BlockInfo, lookback_yielding, reset, BLOCK_SIZE, STATE_AGGREGATE_AVAILABLE, STATE_PREFIX_AVAILABLE };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+use crate::utils;
+use crate::utils::lookback_wait::LookbackWait;
+
+// The prefix of this block is available, and this block has read all its input.
+// Hence other blocks may now overwrite the values of this block.
+const STATE_DONE: u64 = 3;
+
+// In-place compact with the adaptive chained scan.
+// The kept elements of a block are moved to the front, starting at the prefix of that block.
+// As the prefix is at most the start of the block, a block only writes to its own range and the ranges of earlier blocks.
+// Before writing, a block waits until the earlier blocks that overlap its destination have read their input.
+// Those blocks never wait on later blocks, hence this cannot deadlock.
+// output_count is set to the new length of the array.
+pub fn create_task<P: Fn(&u64) -> bool + Sync>(predicate: &P, values: &[AtomicU64], temp: &[BlockInfo], output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  Task::new_dataparallel::<Data<P>>(run::<P>, finish::<P>, Data{ predicate, values, temp, output_count }, (values.len() as u64).div_ceil(BLOCK_SIZE) as u32, false)
+}
+
+struct Data<'a, P> {
+  predicate: &'a P,
+  values: &'a [AtomicU64],
+  temp: &'a [BlockInfo],
+  output_count: &'a AtomicUsize
+}
+
+fn run<P: Fn(&u64) -> bool>(workers: &Workers, task: *const TaskObject<Data<P>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let mut sequential = true;
+  workassisting_loop!(loop_arguments, |block_index| {
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.values.len());
+
+    // Check if we already have an aggregate of the previous block.
+    // If that is the case, then we can perform the compact directly.
+    // Otherwise we perform a reduce-then-scan over this block.
+    let aggregate_start = if !sequential {
+      None // Don't switch back from parallel mode to sequential mode
+    } else if block_index == 0 {
+      Some(0)
+    } else {
+      let previous = block_index - 1;
+      let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+      if previous_state >= STATE_PREFIX_AVAILABLE {
+        Some(data.temp[previous as usize].prefix.load(Ordering::Acquire))
+      } else {
+        None
+      }
+    };
+
+    if let Some(aggregate) = aggregate_start {
+      wait_for_destination(workers, data, block_index, aggregate);
+      let local = compact_inplace_sequential(data.predicate, data.values, start, end, aggregate);
+      data.temp[block_index as usize].prefix.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(STATE_DONE, Ordering::Release);
+    } else {
+      sequential = false;
+      // No other block writes to the range of this block until it is done.
+      let local = count_sequential_by(data.predicate, unsafe { utils::array::as_u64_slice(&data.values[start .. end]) });
+      // Share own local value
+      data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
+
+      // As the previous block may already be partially overwritten by its own compact,
+      // we cannot reduce it ourselves. Instead the lookback yields when that block is slow.
+      let aggregate = lookback_yielding(workers, data.temp, block_index);
+
+      // Make aggregate available
+      data.temp[block_index as usize].prefix.store(aggregate + local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+
+      wait_for_destination(workers, data, block_index, aggregate);
+      compact_inplace_sequential(data.predicate, data.values, start, end, aggregate);
+      data.temp[block_index as usize].state.store(STATE_DONE, Ordering::Release);
+    }
+  });
+}
+
+// Waits until the earlier blocks whose range overlaps the destination of this block, starting at output_start, are done.
+fn wait_for_destination<P>(workers: &Workers, data: &Data<P>, block_index: u32, output_start: usize) {
+  let first = (output_start / BLOCK_SIZE as usize) as u32;
+  for previous in (first .. block_index).rev() {
+    let mut wait = LookbackWait::new();
+    while data.temp[previous as usize].state.load(Ordering::Acquire) != STATE_DONE {
+      if workers.is_cancelled() {
+        return;
+      } else if wait.expired() {
+        std::thread::yield_now();
+      }
+    }
+  }
+}
+
+fn finish<P>(workers: &Workers, task: *mut TaskObject<Data<P>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
@@ -0,0 +1,123 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::compact::{CompactKernel, PredicateKernel};
+use crate::cases::compact::unchanged_half_sized::{ BlockInfo, reset, BLOCK_SIZE, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+use crate::utils::shared_slice::SharedSlice;
+
+// Our half-sized blocks for compact, with an arbitrary element type and predicate.
+pub fn create_task<T: Clone + Send + Sync, P: Fn(&T) -> bool + Sync>(predicate: &P, input: &[T], temp: &[BlockInfo], output: SharedSlice<T>, output_count: &AtomicUsize) -> Task {
+  create_task_with_kernel(PredicateKernel{ predicate, output }, input, temp, output_count)
+}
+
+// As create_task, with the kernels that count and write the selected elements of a block. See CompactKernel.
+pub fn create_task_with_kernel<T: Sync, K: CompactKernel<T>>(kernel: K, input: &[T], temp: &[BlockInfo], output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  let block_count = (input.len() as u64).div_ceil(BLOCK_SIZE) as u32;
+  Task::new_dataparallel::<Data<T, K>>(run::<T, K>, finish::<T, K>, Data{ kernel, input, temp, output_count }, block_count, false)
+}
+
+struct Data<'a, T, K> {
+  kernel: K,
+  input: &'a [T],
+  temp: &'a [BlockInfo],
+  output_count: &'a AtomicUsize
+}
+
+fn run<T, K: CompactKernel<T>>(workers: &Workers, task: *const TaskObject<Data<T, K>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+
+  // Update this after every loop
+  let mut unfinished_index: Option::<u32> = None;
+  let mut unfinished_start = 0;
+  let mut unfinished_end = 0;
+  let mut unfinished_local = 0;
+
+  let mut sequential = true;
+  workassisting_loop!(loop_arguments, |block_index| {
+    // reduce-then-scan
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.input.len());
+
+    // Check if we already have an aggregate of the previous block.
+    // If that is the case, then we can perform the scan directly.
+    // Otherwise we perform a reduce-then-scan over this block.
+    let aggregate_start = if !sequential {
+      None // Don't switch back from parallel mode to sequential mode
+    } else if block_index == 0 {
+      Some(0)
+    } else {
+      let previous = block_index - 1;
+      let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+      if previous_state == STATE_PREFIX_AVAILABLE {
+        Some(data.temp[previous as usize].prefix.load(Ordering::Acquire))
+      } else {
+        None
+      }
+    };
+
+    if let Some(aggregate) = aggregate_start {
+      let local = data.kernel.compact(&data.input[start .. end], aggregate);
+      data.temp[block_index as usize].prefix.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+    } else {
+      sequential = false;
+      let local = data.kernel.count(&data.input[start .. end]);
+      // Share own local value
+      data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
+
+      // Check if it has an unfinished block
+      if let Some(u_index) = unfinished_index {
+        process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
+      }
+
+      // Replace unfinished block with current block
+      unfinished_index = Some(block_index);
+      unfinished_start = start;
+      unfinished_end = end;
+      unfinished_local = local;
+    }
+  });
+
+  // perform last unfinished block
+  if let Some(u_index) = unfinished_index {
+    process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
+  }
+}
+
+#[inline(always)]
+fn process_unfinished_block<T, K: CompactKernel<T>>(workers: &Workers, data: &Data<T, K>, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: usize) {
+  // Find aggregate
+  let mut aggregate = 0;
+  let mut previous = u_index - 1;
+
+  loop {
+    let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+    if previous_state == STATE_PREFIX_AVAILABLE {
+      aggregate += data.temp[previous as usize].prefix.load(Ordering::Acquire);
+      break;
+    } else if previous_state == STATE_AGGREGATE_AVAILABLE {
+      aggregate += data.temp[previous as usize].aggregate.load(Ordering::Acquire);
+      previous -= 1;
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      break;
+    } else {
+      // Continue looping until the state of previous block changes.
+    }
+  }
+
+  // Make aggregate available
+  data.temp[u_index as usize].prefix.store(aggregate + unfinished_local, Ordering::Relaxed);
+  data.temp[u_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+  data.kernel.compact(&data.input[unfinished_start .. unfinished_end], aggregate);
+}
+
+fn finish<T, K>(workers: &Workers, task: *mut TaskObject<Data<T, K>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
@@ -0,0 +1,289 @@
+// Vectorized kernels for the count and compact loops within a block.
+// The predicate is evaluated on multiple values at once; this must match predicate in compact.rs.
+// The implementation is chosen at runtime, based on the features of the processor.
+use core::sync::atomic::AtomicU64;
+#[cfg(not(target_arch = "x86_64"))]
+use core::sync::atomic::Ordering;
+#[cfg(not(target_arch = "x86_64"))]
+use crate::cases::compact::predicate;
+
+#[cfg(target_arch = "x86_64")]
+pub fn count(mask: u64, input: &[u64]) -> usize {
+  if is_x86_feature_detected!("avx512f") {
+    unsafe { count_avx512(mask, input) }
+  } else if is_x86_feature_detected!("avx2") {
+    unsafe { count_avx2(mask, input) }
+  } else if is_x86_feature_detected!("sse4.1") {
+    unsafe { count_sse41(mask, input) }
+  } else {
+    count_remainder(mask, input, 0)
+  }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn count(mask: u64, input: &[u64]) -> usize {
+  let mut count = 0;
+  for &value in input {
+    if predicate(mask, value) {
+      count += 1;
+    }
+  }
+  count
+}
+
+// Writes the values of input that satisfy the predicate to output, starting at output_index.
+// The caller must assure that output has enough space for these values.
+pub fn compact(mask: u64, input: &[u64], output: &[AtomicU64], output_index: usize) -> usize {
+  compact_with::<false>(mask, input, output, output_index)
+}
+
+// Compacts with non-temporal (streaming) stores for the output. See utils::stores.
+pub fn compact_streaming(mask: u64, input: &[u64], output: &[AtomicU64], output_index: usize) -> usize {
+  compact_with::<true>(mask, input, output, output_index)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn compact_with<const STREAMING: bool>(mask: u64, input: &[u64], output: &[AtomicU64], output_index: usize) -> usize {
+  let output_ptr = output.as_ptr() as *mut u64;
+  if is_x86_feature_detected!("avx512f") {
+    unsafe { compact_avx512::<STREAMING>(mask, input, output_ptr, output_index) }
+  } else if is_x86_feature_detected!("avx2") {
+    unsafe { compact_avx2::<STREAMING>(mask, input, output_ptr, output_index) }
+  } else {
+    unsafe {
+      let output_index = compact_remainder::<STREAMING>(mask, input, 0, output_ptr, output_index);
+      fence::<STREAMING>();
+      output_index
+    }
+  }
+}
+
+// Non-temporal stores are not available in the portable implementation, so both variants perform regular stores.
+#[cfg(not(target_arch = "x86_64"))]
+fn compact_with<const STREAMING: bool>(mask: u64, input: &[u64], output: &[AtomicU64], mut output_index: usize) -> usize {
+  for &value in input {
+    if predicate(mask, value) {
+      unsafe { output.get_unchecked(output_index) }.store(value, Ordering::Relaxed);
+      output_index += 1;
+    }
+  }
+  output_index
+}
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+#[cfg(target_arch = "x86_64")]
+use crate::cases::compact::predicate;
+
+// Processes the values in input[start ..] one by one. Used for the remaining values after the vectorized loop.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn count_remainder(mask: u64, input: &[u64], start: usize) -> usize {
+  input[start ..].iter().filter(|&&value| predicate(mask, value)).count()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn compact_remainder<const STREAMING: bool>(mask: u64, input: &[u64], start: usize, output: *mut u64, mut output_index: usize) -> usize {
+  for &value in &input[start ..] {
+    if predicate(mask, value) {
+      store::<STREAMING>(output.add(output_index), value);
+      output_index += 1;
+    }
+  }
+  output_index
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn store<const STREAMING: bool>(pointer: *mut u64, value: u64) {
+  if STREAMING {
+    _mm_stream_si64(pointer as *mut i64, value as i64);
+  } else {
+    *pointer = value;
+  }
+}
+
+// Stores the lanes of values that are set in 'selected' with streaming stores, to consecutive positions in output.
+// There is no masked streaming store of 64-bit lanes, so we store the lanes one by one.
+// The write-combining buffers of the processor still merge these into full cache lines.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn stream_selected(input: *const u64, mut selected: u32, output: *mut u64, mut output_index: usize) -> usize {
+  while selected != 0 {
+    let lane = selected.trailing_zeros() as usize;
+    _mm_stream_si64(output.add(output_index) as *mut i64, *input.add(lane) as i64);
+    output_index += 1;
+    selected &= selected - 1;
+  }
+  output_index
+}
+
+// Non-temporal stores are weakly ordered. The fence assures that they are visible to other threads
+// before the state of the block is published (with a release store).
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn fence<const STREAMING: bool>() {
+  if STREAMING {
+    _mm_sfence();
+  }
+}
+
+// For each mask of four lanes, the indices (in 32-bit lanes) to move the selected 64-bit lanes to the front.
+#[cfg(target_arch = "x86_64")]
+static COMPRESS_PERMUTATIONS: [[i32; 8]; 16] = compress_permutations();
+
+#[cfg(target_arch = "x86_64")]
+const fn compress_permutations() -> [[i32; 8]; 16] {
+  let mut table = [[0; 8]; 16];
+  let mut mask = 0;
+  while mask < 16 {
+    let mut selected = 0;
+    let mut lane: i32 = 0;
+    while lane < 4 {
+      if mask & (1 << lane) != 0 {
+        table[mask][2 * selected] = 2 * lane;
+        table[mask][2 * selected + 1] = 2 * lane + 1;
+        selected += 1;
+      }
+      lane += 1;
+    }
+    mask += 1;
+  }
+  table
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn predicate_avx512(mask: __m512i, value: __m512i) -> __mmask8 {
+  let mut x = value;
+  x = _mm512_xor_si512(x, _mm512_srli_epi64::<11>(x));
+  x = _mm512_xor_si512(x, _mm512_slli_epi64::<7>(x));
+  x = _mm512_xor_si512(x, _mm512_srli_epi64::<5>(x));
+  _mm512_cmpeq_epi64_mask(_mm512_and_si512(x, mask), mask)
+}
+
+// Returns a vector with all bits set in the lanes that satisfy the predicate.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn predicate_avx2(mask: __m256i, value: __m256i) -> __m256i {
+  let mut x = value;
+  x = _mm256_xor_si256(x, _mm256_srli_epi64::<11>(x));
+  x = _mm256_xor_si256(x, _mm256_slli_epi64::<7>(x));
+  x = _mm256_xor_si256(x, _mm256_srli_epi64::<5>(x));
+  _mm256_cmpeq_epi64(_mm256_and_si256(x, mask), mask)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn predicate_sse41(mask: __m128i, value: __m128i) -> __m128i {
+  let mut x = value;
+  x = _mm_xor_si128(x, _mm_srli_epi64::<11>(x));
+  x = _mm_xor_si128(x, _mm_slli_epi64::<7>(x));
+  x = _mm_xor_si128(x, _mm_srli_epi64::<5>(x));
+  _mm_cmpeq_epi64(_mm_and_si128(x, mask), mask)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn count_avx512(mask: u64, input: &[u64]) -> usize {
+  let mask_vector = _mm512_set1_epi64(mask as i64);
+  let pointer = input.as_ptr();
+  let mut count = 0;
+  let mut i = 0;
+  while i + 8 <= input.len() {
+    let selected = predicate_avx512(mask_vector, _mm512_loadu_si512(pointer.add(i) as *const _));
+    count += selected.count_ones() as usize;
+    i += 8;
+  }
+  count + count_remainder(mask, input, i)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn count_avx2(mask: u64, input: &[u64]) -> usize {
+  let mask_vector = _mm256_set1_epi64x(mask as i64);
+  let pointer = input.as_ptr();
+  // The lanes of a selected value are -1, hence we subtract them from the counters.
+  let mut counters = _mm256_setzero_si256();
+  let mut i = 0;
+  while i + 4 <= input.len() {
+    let selected = predicate_avx2(mask_vector, _mm256_loadu_si256(pointer.add(i) as *const __m256i));
+    counters = _mm256_sub_epi64(counters, selected);
+    i += 4;
+  }
+  let sum = _mm_add_epi64(_mm256_castsi256_si128(counters), _mm256_extracti128_si256::<1>(counters));
+  let count = _mm_cvtsi128_si64(_mm_add_epi64(sum, _mm_unpackhi_epi64(sum, sum))) as usize;
+  count + count_remainder(mask, input, i)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn count_sse41(mask: u64, input: &[u64]) -> usize {
+  let mask_vector = _mm_set1_epi64x(mask as i64);
+  let pointer = input.as_ptr();
+  let mut counters = _mm_setzero_si128();
+  let mut i = 0;
+  while i + 2 <= input.len() {
+    let selected = predicate_sse41(mask_vector, _mm_loadu_si128(pointer.add(i) as *const __m128i));
+    counters = _mm_sub_epi64(counters, selected);
+    i += 2;
+  }
+  let count = _mm_cvtsi128_si64(_mm_add_epi64(counters, _mm_unpackhi_epi64(counters, counters))) as usize;
+  count + count_remainder(mask, input, i)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn compact_avx512<const STREAMING: bool>(mask: u64, input: &[u64], output: *mut u64, mut output_index: usize) -> usize {
+  let mask_vector = _mm512_set1_epi64(mask as i64);
+  let pointer = input.as_ptr();
+  let mut i = 0;
+  while i + 8 <= input.len() {
+    let values = _mm512_loadu_si512(pointer.add(i) as *const _);
+    let selected = predicate_avx512(mask_vector, values);
+    if STREAMING {
+      output_index = stream_selected(pointer.add(i), selected as u32, output, output_index);
+    } else {
+      let count = selected.count_ones() as usize;
+      // Move the selected values to the front, and only store those lanes.
+      // We don't use a compressing store, as that is slow on some processors.
+      let compressed = _mm512_maskz_compress_epi64(selected, values);
+      _mm512_mask_storeu_epi64(output.add(output_index) as *mut _, ((1u32 << count) - 1) as __mmask8, compressed);
+      output_index += count;
+    }
+    i += 8;
+  }
+  let output_index = compact_remainder::<STREAMING>(mask, input, i, output, output_index);
+  fence::<STREAMING>();
+  output_index
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn compact_avx2<const STREAMING: bool>(mask: u64, input: &[u64], output: *mut u64, mut output_index: usize) -> usize {
+  let mask_vector = _mm256_set1_epi64x(mask as i64);
+  let lanes = _mm256_set_epi64x(3, 2, 1, 0);
+  let pointer = input.as_ptr();
+  let mut i = 0;
+  while i + 4 <= input.len() {
+    let values = _mm256_loadu_si256(pointer.add(i) as *const __m256i);
+    let selected = _mm256_movemask_pd(_mm256_castsi256_pd(predicate_avx2(mask_vector, values))) as usize;
+    if STREAMING {
+      output_index = stream_selected(pointer.add(i), selected as u32, output, output_index);
+    } else {
+      let count = selected.count_ones() as usize;
+      // Move the selected values to the front, and only store those lanes.
+      // Storing all lanes could overwrite values written by the thread processing the next block.
+      let permutation = _mm256_loadu_si256(COMPRESS_PERMUTATIONS[selected].as_ptr() as *const __m256i);
+      let compressed = _mm256_permutevar8x32_epi32(values, permutation);
+      let store_mask = _mm256_cmpgt_epi64(_mm256_set1_epi64x(count as i64), lanes);
+      _mm256_maskstore_epi64(output.add(output_index) as *mut i64, store_mask, compressed);
+      output_index += count;
+    }
+    i += 4;
+  }
+  let output_index = compact_remainder::<STREAMING>(mask, input, i, output, output_index);
+  fence::<STREAMING>();
+  output_index
+}
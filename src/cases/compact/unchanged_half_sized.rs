@@ -44,7 +44,7 @@ pub fn create_task(mask: u64, input: &[u64], temp: &[BlockInfo], output: &[Atomi
   Task::new_dataparallel::<Data>(run, finish, Data{ mask, input, temp, output, output_count }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   workassisting_loop!(loop_arguments, |block_index| {
     // Local scan
@@ -74,6 +74,9 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
         } else if previous_state == STATE_AGGREGATE_AVAILABLE {
           aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
           previous = previous - 1;
+        } else if workers.is_cancelled() {
+          // The task is cancelled, hence the previous block may never be published.
+          break;
         } else {
           // Continue looping until the state of previous block changes.
         }
@@ -0,0 +1,152 @@
+use core::cell::RefCell;
+use core::sync::atomic::{Ordering, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::cases::compact;
+use crate::core::worker::*;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+use crate::utils::shared_slice::SharedSlice;
+
+pub mod our_chained;
+
+pub const SIZE: usize = 1024 * 1024 * 32;
+// The number of distinct values in the input. The chance that an element equals its predecessor is 1 / VALUES.
+const VALUES: u64 = 4;
+
+// Removes adjacent duplicates, like Vec::dedup.
+pub fn run() {
+  let size = SIZE;
+  let input = create_input(size);
+  let temp = compact::chained::create_temp(size);
+  let output = RefCell::new(vec![0; size].into_boxed_slice());
+  let equal = |a: &u64, b: &u64| a == b;
+
+  let name = "Dedup (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        let mut output = output.borrow_mut();
+        let count = dedup_sequential_by(&equal, &input, None, &SharedSlice::new(&mut output), 0);
+        compute_output(&output, count)
+      }
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let mut output = output.borrow_mut();
+      let output_count = AtomicUsize::new(0);
+      let task = our_chained::create_task(&equal, &input, &temp, SharedSlice::new(&mut output), &output_count);
+      Workers::run(thread_count, task);
+      compute_output(&output, output_count.load(Ordering::Relaxed))
+    });
+}
+
+pub fn create_input(size: usize) -> Box<[u64]> {
+  (0..size).map(|x| random(x as u64) as u64 % VALUES).collect()
+}
+
+pub fn compute_output<T: Copy + Default>(output: &[T], count: usize) -> (usize, T, T, T) {
+  if count == 0 {
+    (0, T::default(), T::default(), T::default())
+  } else {
+    (count, output[0], output[count / 2], output[count - 1])
+  }
+}
+
+// Writes the elements of the input that are not equal to their predecessor to the output, starting at output_index.
+// To deduplicate by a key function, pass |a, b| key(a) == key(b) as 'equal'.
+// 'previous' is the element before the input, or None if the input starts at the start of the array.
+// The caller must assure that no other thread writes to that part of the output concurrently.
+// Returns the index after the last written element.
+pub fn dedup_sequential_by<T: Clone, E: Fn(&T, &T) -> bool>(equal: &E, input: &[T], previous: Option<&T>, output: &SharedSlice<T>, output_index: usize) -> usize {
+  let mut index = output_index;
+  let mut previous = previous;
+  for value in input {
+    if previous.is_none_or(|previous| !equal(previous, value)) {
+      unsafe { output.write(index, value.clone()) };
+      index += 1;
+    }
+    previous = Some(value);
+  }
+  index
+}
+
+// The predecessor of the element at 'start', which is the last element of the previous block if 'start' is the start of a block.
+pub fn previous_value<T>(input: &[T], start: usize) -> Option<&T> {
+  if start == 0 { None } else { Some(&input[start - 1]) }
+}
+
+pub fn count_unique_sequential_by<T, E: Fn(&T, &T) -> bool>(equal: &E, input: &[T], previous: Option<&T>) -> usize {
+  let mut count = 0;
+  let mut previous = previous;
+  for value in input {
+    if previous.is_none_or(|previous| !equal(previous, value)) {
+      count += 1;
+    }
+    previous = Some(value);
+  }
+  count
+}
+
+fn random(mut seed: u64) -> u32 {
+  seed ^= seed << 13;
+  seed ^= seed >> 17;
+  seed ^= seed << 5;
+  seed as u32
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicUsize};
+  use crate::cases::compact;
+  use crate::cases::dedup::*;
+  use crate::utils::testing::thread_counts;
+
+  // Deduplicates the input with the parallel implementation, for each thread count,
+  // and checks that this gives the same result as Vec::dedup.
+  fn dedup(input: &[u64]) {
+    let mut expected = input.to_vec();
+    expected.dedup();
+    let temp = compact::chained::create_temp(input.len());
+    let equal = |a: &u64, b: &u64| a == b;
+    for thread_count in thread_counts() {
+      let mut output = vec![u64::MAX; input.len()];
+      let output_count = AtomicUsize::new(usize::MAX);
+      Workers::run(thread_count, our_chained::create_task(&equal, input, &temp, SharedSlice::new(&mut output), &output_count));
+      let count = output_count.load(Ordering::Relaxed);
+      assert_eq!(&output[0 .. count], &expected[..]);
+    }
+  }
+
+  #[test]
+  fn empty() {
+    dedup(&[]);
+    assert_eq!(compute_output::<u64>(&[], 0), (0, 0, 0, 0));
+  }
+
+  #[test]
+  fn sizes() {
+    let block_size = compact::chained::BLOCK_SIZE as usize;
+    for size in [1, block_size - 1, block_size, block_size + 1, 100_003] {
+      dedup(&create_input(size));
+    }
+  }
+
+  #[test]
+  fn runs_crossing_blocks() {
+    // Runs of 5000 elements cross the boundaries of the blocks of 4096 elements.
+    let input: Vec<u64> = (0 .. 100_000).map(|x| x / 5000).collect();
+    dedup(&input);
+    // A run starts at the last element of each block, hence the first element of each block equals the last element of the previous block.
+    let block_size = compact::chained::BLOCK_SIZE;
+    let input: Vec<u64> = (0 .. 5 * block_size).map(|x| ((x + 1) / block_size) % 2).collect();
+    dedup(&input);
+    // Runs of two elements, consisting of the last element of a block and the first element of the next block.
+    let input: Vec<u64> = (0 .. 5 * block_size).map(|x| if (x + 1) % block_size < 2 { (x + 1) / block_size } else { 0 }).collect();
+    dedup(&input);
+  }
+
+  #[test]
+  fn all_equal() {
+    dedup(&vec![3; 3 * compact::chained::BLOCK_SIZE as usize + 5]);
+  }
+}
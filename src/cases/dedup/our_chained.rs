@@ -0,0 +1,43 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::compact::chained::{ BlockInfo, adaptive_scan, block_count, block_range, reset };
+use crate::cases::dedup::{count_unique_sequential_by, dedup_sequential_by, previous_value};
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::utils::shared_slice::SharedSlice;
+
+// Removes adjacent duplicates with the adaptive chained scan of compact::our_chained_by.
+// Whether an element is kept depends on its predecessor, which is the last element of the previous block
+// for the first element of a block. Blocks only read that element, hence they remain independent.
+pub fn create_task<T: Clone + Send + Sync, E: Fn(&T, &T) -> bool + Sync>(equal: &E, input: &[T], temp: &[BlockInfo], output: SharedSlice<T>, output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  Task::new_dataparallel::<Data<T, E>>(run::<T, E>, finish::<T, E>, Data{ equal, input, temp, output, output_count }, block_count(input.len()), false)
+}
+
+struct Data<'a, T, E> {
+  equal: &'a E,
+  input: &'a [T],
+  temp: &'a [BlockInfo],
+  output: SharedSlice<'a, T>,
+  output_count: &'a AtomicUsize
+}
+
+fn run<T: Clone, E: Fn(&T, &T) -> bool>(workers: &Workers, task: *const TaskObject<Data<T, E>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      count_unique_sequential_by(data.equal, &data.input[start .. end], previous_value(data.input, start))
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      dedup_sequential_by(data.equal, &data.input[start .. end], previous_value(data.input, start), &data.output, prefix)
+    }
+  );
+}
+
+fn finish<T, E>(workers: &Workers, task: *mut TaskObject<Data<T, E>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
@@ -0,0 +1,107 @@
+use core::cell::RefCell;
+use core::sync::atomic::{Ordering, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::cases::{compact, split};
+use crate::cases::compact::Record;
+use crate::core::worker::*;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+use crate::utils::shared_slice::SharedSlice;
+
+pub mod our_histogram;
+
+pub const SIZE: usize = 1024 * 1024 * 32;
+
+// Histograms and counting sorts for small key ranges.
+pub fn run() {
+  run_with::<16>();
+  run_with::<256>();
+}
+
+fn run_with<const K: usize>() {
+  let size = SIZE;
+  let input = compact::create_input(size);
+  let histogram: Box<[AtomicUsize]> = (0 .. K).map(|_| AtomicUsize::new(0)).collect();
+  let key = |value: &u64| (*value as usize) % K;
+
+  let name = "Histogram (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", k = " + &K.to_string() + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        let mut histogram = [0; K];
+        split::histogram_sequential(&key, &input, &mut histogram);
+        compute_output(&histogram)
+      }
+    )
+    .parallel("Thread-local histograms", 7, None, true, || {}, |thread_count| {
+      Workers::run(thread_count, our_histogram::create_task::<u64, _, K>(&key, &input, &histogram));
+      let histogram: [usize; K] = core::array::from_fn(|bucket| histogram[bucket].load(Ordering::Relaxed));
+      compute_output(&histogram)
+    });
+
+  // A stable counting sort of records by their key. This is a k-way split where the key is the bucket:
+  // the offset of an element in the output is the exclusive scan over the histograms of the blocks,
+  // which split::our_chained computes with the adaptive chained scan.
+  let size = SIZE / 8;
+  let records = compact::create_records(size);
+  let temp = compact::chained::create_histogram_temp::<K>(size);
+  let output = RefCell::new(vec![Record::default(); size].into_boxed_slice());
+  let bucket_offsets: Box<[AtomicUsize]> = (0 .. K).map(|_| AtomicUsize::new(0)).collect();
+  let record_key = |record: &Record| (record.key as usize) % K;
+
+  let name = "Counting sort (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", k = " + &K.to_string() + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || split::reference_sequential_single::<Record, _, K>(&record_key, &records, &mut output.borrow_mut())
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let mut output = output.borrow_mut();
+      let task = split::our_chained::create_task::<Record, _, K>(&record_key, &records, &temp, SharedSlice::new(&mut output), &bucket_offsets);
+      Workers::run(thread_count, task);
+      let offsets: Vec<usize> = bucket_offsets.iter().map(|offset| offset.load(Ordering::Relaxed)).collect();
+      split::compute_output(&offsets, &output)
+    });
+}
+
+pub fn compute_output<const K: usize>(histogram: &[usize; K]) -> (usize, usize, usize) {
+  (histogram[0], histogram[K / 2], histogram[K - 1])
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicUsize};
+  use crate::cases::{compact, split};
+  use crate::cases::histogram::*;
+  use crate::utils::testing::thread_counts;
+
+  // Computes the histogram with the parallel implementation, for each thread count,
+  // and checks that this gives the same result as histogram_sequential.
+  fn histogram<F: Fn(&u64) -> usize + Sync, const K: usize>(input: &[u64], key: F) {
+    let mut expected = [0; K];
+    split::histogram_sequential(&key, input, &mut expected);
+    for thread_count in thread_counts() {
+      let histogram: Box<[AtomicUsize]> = (0 .. K).map(|_| AtomicUsize::new(usize::MAX)).collect();
+      Workers::run(thread_count, our_histogram::create_task::<u64, _, K>(&key, input, &histogram));
+      let histogram: [usize; K] = core::array::from_fn(|bucket| histogram[bucket].load(Ordering::Relaxed));
+      assert_eq!(histogram, expected);
+    }
+  }
+
+  #[test]
+  fn sizes() {
+    let block_size = compact::chained::BLOCK_SIZE as usize;
+    for size in [0, 1, block_size - 1, block_size, block_size + 1, 100_003] {
+      let input = compact::create_input(size);
+      histogram::<_, 16>(&input, |value| (*value as usize) % 16);
+      histogram::<_, 256>(&input, |value| (*value as usize) % 256);
+    }
+  }
+
+  #[test]
+  fn single_bucket() {
+    histogram::<_, 4>(&compact::create_input(3 * compact::chained::BLOCK_SIZE as usize + 5), |_| 3);
+  }
+}
@@ -0,0 +1,46 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::split::histogram_sequential;
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+
+const BLOCK_SIZE: u64 = 1024 * 16;
+
+// Counts the number of elements per bucket.
+// Each thread counts in a local histogram, and adds that to the shared histogram when it stops working on the task,
+// such that threads only touch the shared counters once. With a single thread, this is the sequential loop and K atomic additions.
+// The blocks are independent, hence threads may claim the blocks of their own NUMA node first.
+pub fn create_task<T: Sync, F: Fn(&T) -> usize + Sync, const K: usize>(key: &F, input: &[T], histogram: &[AtomicUsize]) -> Task {
+  assert_eq!(histogram.len(), K);
+  for counter in histogram {
+    counter.store(0, Ordering::Relaxed);
+  }
+  let block_count = (input.len() as u64).div_ceil(BLOCK_SIZE) as u32;
+  Task::new_dataparallel_node_local::<Data<T, F>>(run::<T, F, K>, finish::<T, F>, Data{ key, input, histogram }, block_count)
+}
+
+struct Data<'a, T, F> {
+  key: &'a F,
+  input: &'a [T],
+  histogram: &'a [AtomicUsize]
+}
+
+fn run<T, F: Fn(&T) -> usize, const K: usize>(_workers: &Workers, task: *const TaskObject<Data<T, F>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let mut histogram = [0; K];
+  workassisting_loop!(loop_arguments, |block_index| {
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.input.len());
+    histogram_sequential(data.key, &data.input[start .. end], &mut histogram);
+  });
+  for (total, count) in data.histogram.iter().zip(histogram) {
+    if count != 0 {
+      total.fetch_add(count, Ordering::Relaxed);
+    }
+  }
+}
+
+fn finish<T, F>(workers: &Workers, task: *mut TaskObject<Data<T, F>>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
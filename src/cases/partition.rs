@@ -0,0 +1,123 @@
+use core::cell::RefCell;
+use core::sync::atomic::{Ordering, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::cases::compact;
+use crate::core::worker::*;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+use crate::utils::shared_slice::SharedSlice;
+
+pub mod our_chained;
+
+pub const SIZE: usize = 1024 * 1024 * 32;
+
+// A stable partition, which writes the elements that satisfy the predicate to one output array,
+// and the other elements to a second output array, both in the order of the input.
+pub fn run() {
+  let size = SIZE;
+  for ratio in [2, 8] {
+    let input = compact::create_input(size);
+    let temp = compact::chained::create_temp(size);
+    let output_true = RefCell::new(vec![0; size].into_boxed_slice());
+    let output_false = RefCell::new(vec![0; size].into_boxed_slice());
+    let mask = ratio - 1; // Assumes ratio is a power of two
+    let predicate = |value: &u64| compact::predicate(mask, *value);
+
+    let name = "Partition (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", r = 1/" + &ratio.to_string() + ")";
+    benchmark(
+        ChartStyle::WithKey,
+        &name,
+        || {},
+        || {
+          let mut output_true = output_true.borrow_mut();
+          let mut output_false = output_false.borrow_mut();
+          let count = partition_sequential_by(&predicate, &input, &SharedSlice::new(&mut output_true), 0, &SharedSlice::new(&mut output_false), 0);
+          compute_output(&output_true, &output_false, input.len(), count)
+        }
+      )
+      .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+        let mut output_true = output_true.borrow_mut();
+        let mut output_false = output_false.borrow_mut();
+        let output_count = AtomicUsize::new(0);
+        let task = our_chained::create_task(&predicate, &input, &temp, SharedSlice::new(&mut output_true), SharedSlice::new(&mut output_false), &output_count);
+        Workers::run(thread_count, task);
+        compute_output(&output_true, &output_false, input.len(), output_count.load(Ordering::Relaxed))
+      });
+  }
+}
+
+// input_length is the length of the input, and count is the number of elements in output_true.
+pub fn compute_output<T: Copy + Default>(output_true: &[T], output_false: &[T], input_length: usize, count: usize) -> (usize, T, T, T, T) {
+  let (first_true, last_true) = first_and_last(output_true, count);
+  let (first_false, last_false) = first_and_last(output_false, input_length - count);
+  (count, first_true, last_true, first_false, last_false)
+}
+
+fn first_and_last<T: Copy + Default>(output: &[T], count: usize) -> (T, T) {
+  if count == 0 {
+    (T::default(), T::default())
+  } else {
+    (output[0], output[count - 1])
+  }
+}
+
+// Writes the elements of the input that satisfy the predicate to output_true, starting at true_index,
+// and the other elements to output_false, starting at false_index.
+// The caller must assure that no other thread writes to those parts of the outputs concurrently.
+// Returns the index in output_true after the last written element.
+pub fn partition_sequential_by<T: Clone, P: Fn(&T) -> bool>(predicate: &P, input: &[T], output_true: &SharedSlice<T>, true_index: usize, output_false: &SharedSlice<T>, false_index: usize) -> usize {
+  let mut true_index = true_index;
+  let mut false_index = false_index;
+  for value in input {
+    if predicate(value) {
+      unsafe { output_true.write(true_index, value.clone()) };
+      true_index += 1;
+    } else {
+      unsafe { output_false.write(false_index, value.clone()) };
+      false_index += 1;
+    }
+  }
+  true_index
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicUsize};
+  use crate::cases::compact;
+  use crate::cases::partition::*;
+  use crate::utils::testing::thread_counts;
+
+  // Partitions the input with the parallel implementation, for each thread count,
+  // and checks that both outputs contain their elements in the order of the input.
+  fn partition<P: Fn(&u64) -> bool + Sync>(input: &[u64], predicate: P) {
+    let expected_true: Vec<u64> = input.iter().copied().filter(|value| predicate(value)).collect();
+    let expected_false: Vec<u64> = input.iter().copied().filter(|value| !predicate(value)).collect();
+    let temp = compact::chained::create_temp(input.len());
+    for thread_count in thread_counts() {
+      let mut output_true = vec![u64::MAX; input.len()];
+      let mut output_false = vec![u64::MAX; input.len()];
+      let output_count = AtomicUsize::new(usize::MAX);
+      Workers::run(thread_count, our_chained::create_task(&predicate, input, &temp, SharedSlice::new(&mut output_true), SharedSlice::new(&mut output_false), &output_count));
+      let count = output_count.load(Ordering::Relaxed);
+      assert_eq!(count, expected_true.len());
+      assert_eq!(&output_true[0 .. count], &expected_true[..]);
+      assert_eq!(&output_false[0 .. input.len() - count], &expected_false[..]);
+    }
+  }
+
+  #[test]
+  fn sizes() {
+    let block_size = compact::chained::BLOCK_SIZE as usize;
+    for size in [0, 1, block_size - 1, block_size, block_size + 1, 100_003] {
+      let input = compact::create_input(size);
+      partition(&input, |value| compact::predicate(1, *value));
+      partition(&input, |value| compact::predicate(7, *value));
+    }
+  }
+
+  #[test]
+  fn one_side_empty() {
+    let input = compact::create_input(3 * compact::chained::BLOCK_SIZE as usize + 5);
+    partition(&input, |_| true);
+    partition(&input, |_| false);
+  }
+}
@@ -0,0 +1,46 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::compact::count_sequential_by;
+use crate::cases::partition::partition_sequential_by;
+use crate::cases::compact::chained::{ BlockInfo, adaptive_scan, block_count, block_range, reset };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::utils::shared_slice::SharedSlice;
+
+// Stable partition using the adaptive chained scan of compact::our_chained_by.
+// The lookback of compact::chained computes the number of elements before a block that satisfy the predicate.
+// The number of elements before the block that do not satisfy the predicate follows from the start index of the block.
+// output_count is set to the number of elements in output_true.
+pub fn create_task<T: Clone + Send + Sync, P: Fn(&T) -> bool + Sync>(predicate: &P, input: &[T], temp: &[BlockInfo], output_true: SharedSlice<T>, output_false: SharedSlice<T>, output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  Task::new_dataparallel::<Data<T, P>>(run::<T, P>, finish::<T, P>, Data{ predicate, input, temp, output_true, output_false, output_count }, block_count(input.len()), false)
+}
+
+struct Data<'a, T, P> {
+  predicate: &'a P,
+  input: &'a [T],
+  temp: &'a [BlockInfo],
+  output_true: SharedSlice<'a, T>,
+  output_false: SharedSlice<'a, T>,
+  output_count: &'a AtomicUsize
+}
+
+fn run<T: Clone, P: Fn(&T) -> bool>(workers: &Workers, task: *const TaskObject<Data<T, P>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      count_sequential_by(data.predicate, &data.input[start .. end])
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      partition_sequential_by(data.predicate, &data.input[start .. end], &data.output_true, prefix, &data.output_false, start - prefix)
+    }
+  );
+}
+
+fn finish<T, P>(workers: &Workers, task: *mut TaskObject<Data<T, P>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(count, Ordering::Relaxed);
+  workers.finish();
+}
@@ -0,0 +1,76 @@
+use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::core::graph::TaskGraph;
+use crate::core::worker::*;
+use crate::cases::{compact, scan};
+use crate::utils;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+
+// Runs a prefix-sum, a compact on its output, and a prefix-sum on the compacted values.
+// This compares calling Workers::run for each phase with running the phases as a task graph,
+// where the next phase starts without stopping and restarting the threads.
+pub const SIZE: usize = 1024 * 1024 * 16;
+const MASK: u64 = 1;
+
+pub fn run() {
+  let size = SIZE;
+  let input = create_input(size);
+  let scan_temp = scan::chained::create_temp(size);
+  let compact_temp = compact::chained::create_temp(size);
+  let scanned = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let output = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let output_count = AtomicUsize::new(0);
+
+  let name = "Scan-compact-scan (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || reference_sequential_single(&input, &scanned, &output)
+    )
+    .parallel("Separate runs", 4, None, false, || {}, |thread_count| {
+      Workers::run(thread_count, scan::our_chained::init_single(&input, &scan_temp, &scanned));
+      let compact_input = unsafe { utils::array::as_u64_slice(&scanned) };
+      Workers::run(thread_count, compact::our_chained::create_task(MASK, compact_input, &compact_temp, &output, &output_count));
+      let count = output_count.load(Ordering::Relaxed);
+      Workers::run(thread_count, scan::our_chained::init_single(&output[0 .. count], &scan_temp, &output[0 .. count]));
+      compute_output(&output, count)
+    })
+    .parallel("Task graph", 7, None, true, || {}, |thread_count| {
+      let mut graph = TaskGraph::new();
+      let first = graph.add(|| scan::our_chained::init_single(&input, &scan_temp, &scanned), &[]);
+      let second = graph.add(|| {
+        let compact_input = unsafe { utils::array::as_u64_slice(&scanned) };
+        compact::our_chained::create_task(MASK, compact_input, &compact_temp, &output, &output_count)
+      }, &[first]);
+      graph.add(|| {
+        let count = output_count.load(Ordering::Relaxed);
+        scan::our_chained::init_single(&output[0 .. count], &scan_temp, &output[0 .. count])
+      }, &[second]);
+      graph.run(thread_count);
+      compute_output(&output, output_count.load(Ordering::Relaxed))
+    });
+}
+
+pub fn create_input(size: usize) -> Box<[AtomicU64]> {
+  // Small values, such that the prefix-sum of the prefix-sums does not overflow.
+  (0 .. size).map(|x| AtomicU64::new(random(x as u64) as u64 & 0xFFFF)).collect()
+}
+
+pub fn compute_output(output: &[AtomicU64], count: usize) -> (usize, u64) {
+  compact::compute_output(output, count)
+}
+
+pub fn reference_sequential_single(input: &[AtomicU64], scanned: &[AtomicU64], output: &[AtomicU64]) -> (usize, u64) {
+  scan::scan_sequential(input, 0, scanned);
+  let count = compact::compact_sequential(MASK, unsafe { utils::array::as_u64_slice(scanned) }, output, 0);
+  scan::scan_sequential(&output[0 .. count], 0, &output[0 .. count]);
+  compute_output(output, count)
+}
+
+fn random(mut seed: u64) -> u32 {
+  seed ^= seed << 13;
+  seed ^= seed >> 17;
+  seed ^= seed << 5;
+  seed as u32
+}
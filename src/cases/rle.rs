@@ -0,0 +1,218 @@
+use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::cases::{compact, scan};
+use crate::core::worker::*;
+use crate::utils;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+
+pub mod decode;
+pub mod encode;
+
+pub const SIZE: usize = 1024 * 1024 * 32;
+// The average length of a run in the input.
+const RUN_LENGTH: u32 = 8;
+
+// Run-length encoding of a sorted column, into the values and the lengths of the runs, and decoding it back.
+pub fn run() {
+  let size = SIZE;
+  let input = create_input(size);
+  let encode_temp = compact::chained::create_temp(size);
+  let decode_temp = scan::chained::create_temp(size);
+  let values = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let lengths = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let ends = unsafe { utils::array::alloc_undef_u64_array(size) };
+  let output = unsafe { utils::array::alloc_undef_u64_array(size) };
+
+  // The benchmarks only compare the parallel implementations with the sequential implementations,
+  // hence we first check that the sequential implementations round trip.
+  let count = encode_sequential(&input, &values, &lengths);
+  decode_sequential(&values[0 .. count], &lengths[0 .. count], &output);
+  assert!(is_equal(&input, &output));
+
+  let name = "RLE encode (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        let count = encode_sequential(&input, &values, &lengths);
+        compute_encoded_output(&values, &lengths, count)
+      }
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let output_count = AtomicUsize::new(0);
+      let task = encode::create_task(&input, &encode_temp, &values, &lengths, &output_count);
+      Workers::run(thread_count, task);
+      compute_encoded_output(&values, &lengths, output_count.load(Ordering::Relaxed))
+    });
+
+  let name = "RLE decode (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || {
+        decode_sequential(&values[0 .. count], &lengths[0 .. count], &output);
+        compute_output(&output)
+      }
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let graph = decode::create_graph(&values[0 .. count], &lengths[0 .. count], &decode_temp, &ends, &output);
+      graph.run(thread_count);
+      compute_output(&output)
+    });
+
+  // The output is now written by the parallel decode, from the values and lengths written by the parallel encode.
+  assert!(is_equal(&input, &output));
+}
+
+// A sorted column, with runs of on average RUN_LENGTH elements.
+pub fn create_input(size: usize) -> Box<[u64]> {
+  let mut value = 0;
+  (0 .. size).map(|x| {
+    if random(x as u64).is_multiple_of(RUN_LENGTH) {
+      value += 1;
+    }
+    value
+  }).collect()
+}
+
+fn is_equal(input: &[u64], output: &[AtomicU64]) -> bool {
+  input.iter().zip(output).all(|(x, y)| *x == y.load(Ordering::Relaxed))
+}
+
+pub fn compute_output(output: &[AtomicU64]) -> u64 {
+  output[0].load(Ordering::Relaxed) + output[98238].load(Ordering::Relaxed) + output[output.len() - 123].load(Ordering::Relaxed) + output[output.len() - 1].load(Ordering::Relaxed)
+}
+
+pub fn compute_encoded_output(values: &[AtomicU64], lengths: &[AtomicU64], count: usize) -> (usize, u64, u64) {
+  (count, compact::compute_output(values, count).1, compact::compute_output(lengths, count).1)
+}
+
+pub fn encode_sequential(input: &[u64], values: &[AtomicU64], lengths: &[AtomicU64]) -> usize {
+  let mut count = 0;
+  let mut run_start = 0;
+  for i in 1 ..= input.len() {
+    if i == input.len() || input[i] != input[i - 1] {
+      values[count].store(input[run_start], Ordering::Relaxed);
+      lengths[count].store((i - run_start) as u64, Ordering::Relaxed);
+      count += 1;
+      run_start = i;
+    }
+  }
+  count
+}
+
+// Writes the first element of each run in the input to values, and the index of that element to starts,
+// starting at output_index. 'previous' is the element before the input, or None if the input starts at
+// the start of the array, and input_offset is the index of the first element of the input in the array.
+// Returns the index after the last written run.
+pub fn encode_starts_sequential(input: &[u64], input_offset: usize, previous: Option<&u64>, values: &[AtomicU64], starts: &[AtomicU64], output_index: usize) -> usize {
+  let mut index = output_index;
+  let mut previous = previous;
+  for (i, value) in input.iter().enumerate() {
+    if previous != Some(value) {
+      values[index].store(*value, Ordering::Relaxed);
+      starts[index].store((input_offset + i) as u64, Ordering::Relaxed);
+      index += 1;
+    }
+    previous = Some(value);
+  }
+  index
+}
+
+pub fn decode_sequential(values: &[AtomicU64], lengths: &[AtomicU64], output: &[AtomicU64]) {
+  let mut index = 0;
+  for (value, length) in values.iter().zip(lengths) {
+    let value = value.load(Ordering::Relaxed);
+    let end = index + length.load(Ordering::Relaxed) as usize;
+    for element in &output[index .. end] {
+      element.store(value, Ordering::Relaxed);
+    }
+    index = end;
+  }
+}
+
+fn random(mut seed: u64) -> u32 {
+  seed ^= seed << 13;
+  seed ^= seed >> 17;
+  seed ^= seed << 5;
+  seed as u32
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+  use crate::cases::{compact, scan};
+  use crate::cases::rle::*;
+  use crate::utils::testing::thread_counts;
+
+  fn alloc(size: usize) -> Box<[AtomicU64]> {
+    (0 .. size).map(|_| AtomicU64::new(u64::MAX)).collect()
+  }
+
+  fn load(array: &[AtomicU64]) -> Vec<u64> {
+    array.iter().map(|value| value.load(Ordering::Relaxed)).collect()
+  }
+
+  // Encodes and decodes the input with the parallel implementations, for each thread count,
+  // and checks that this gives the sequential encoding and the original input.
+  fn round_trip(input: &[u64]) {
+    let size = input.len();
+    let expected_values = alloc(size);
+    let expected_lengths = alloc(size);
+    let expected_count = encode_sequential(input, &expected_values, &expected_lengths);
+
+    let encode_temp = compact::chained::create_temp(size);
+    let decode_temp = scan::chained::create_temp(size);
+    for thread_count in thread_counts() {
+      let values = alloc(size);
+      let lengths = alloc(size);
+      let ends = alloc(size);
+      let output = alloc(size);
+      let output_count = AtomicUsize::new(0);
+
+      Workers::run(thread_count, encode::create_task(input, &encode_temp, &values, &lengths, &output_count));
+      let count = output_count.load(Ordering::Relaxed);
+      assert_eq!(count, expected_count);
+      assert_eq!(load(&values[0 .. count]), load(&expected_values[0 .. count]));
+      assert_eq!(load(&lengths[0 .. count]), load(&expected_lengths[0 .. count]));
+
+      decode::create_graph(&values[0 .. count], &lengths[0 .. count], &decode_temp, &ends, &output).run(thread_count);
+      assert_eq!(load(&output), input);
+    }
+  }
+
+  #[test]
+  fn empty() {
+    round_trip(&[]);
+  }
+
+  #[test]
+  fn single_run() {
+    round_trip(&[7]);
+    round_trip(&vec![7; 3 * compact::chained::BLOCK_SIZE as usize + 5]);
+  }
+
+  #[test]
+  fn runs_crossing_blocks() {
+    // Runs of 5000 elements cross the boundaries of the blocks of 4096 elements.
+    let input: Vec<u64> = (0 .. 100_000).map(|x| x / 5000).collect();
+    round_trip(&input);
+    // A run that starts at the last element of a block.
+    let boundary = compact::chained::BLOCK_SIZE;
+    let input: Vec<u64> = (0 .. 3 * boundary).map(|x| if x < boundary - 1 { 0 } else { 1 }).collect();
+    round_trip(&input);
+  }
+
+  #[test]
+  fn distinct_values() {
+    let input: Vec<u64> = (0 .. 10_000).collect();
+    round_trip(&input);
+  }
+
+  #[test]
+  fn sorted_column() {
+    round_trip(&create_input(300_001));
+  }
+}
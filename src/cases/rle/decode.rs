@@ -0,0 +1,64 @@
+use core::sync::atomic::{Ordering, AtomicU64};
+use crate::cases::scan;
+use crate::cases::scan::chained::BlockInfo;
+use crate::core::graph::TaskGraph;
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+
+const BLOCK_SIZE: u64 = 1024 * 16;
+
+// Run-length decoding in two phases.
+// The first phase computes the end of each run with the adaptive chained scan over the lengths.
+// The second phase fills the output. It divides the output, not the runs, in blocks,
+// such that long runs do not cause a load imbalance. A block finds its first run with a binary search over the ends.
+// The blocks are independent, hence threads may claim the blocks of their own NUMA node first.
+// 'ends' is used as temporary storage, and must be at least as long as 'lengths'.
+// The length of the output must be the sum of the lengths.
+pub fn create_graph<'a>(values: &'a [AtomicU64], lengths: &'a [AtomicU64], temp: &'a [BlockInfo], ends: &'a [AtomicU64], output: &'a [AtomicU64]) -> TaskGraph<'a> {
+  assert_eq!(values.len(), lengths.len());
+  let ends = &ends[0 .. lengths.len()];
+  let mut graph = TaskGraph::new();
+  let scan = graph.add(move || scan::our_chained::init_single(lengths, temp, ends), &[]);
+  graph.add(move || create_fill_task(values, ends, output), &[scan]);
+  graph
+}
+
+fn create_fill_task<'a>(values: &'a [AtomicU64], ends: &'a [AtomicU64], output: &'a [AtomicU64]) -> Task {
+  let total = ends.last().map_or(0, |end| end.load(Ordering::Relaxed));
+  assert_eq!(total as usize, output.len());
+  let block_count = (output.len() as u64).div_ceil(BLOCK_SIZE) as u32;
+  Task::new_dataparallel_node_local::<Data>(run_fill, finish_fill, Data{ values, ends, output }, block_count)
+}
+
+struct Data<'a> {
+  values: &'a [AtomicU64],
+  ends: &'a [AtomicU64],
+  output: &'a [AtomicU64]
+}
+
+fn run_fill(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  workassisting_loop!(loop_arguments, |block_index| {
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.output.len());
+
+    // The first run that ends after the start of this block.
+    let mut run = data.ends.partition_point(|run_end| run_end.load(Ordering::Relaxed) as usize <= start);
+    let mut index = start;
+    while index < end {
+      let run_end = (data.ends[run].load(Ordering::Relaxed) as usize).min(end);
+      let value = data.values[run].load(Ordering::Relaxed);
+      for element in &data.output[index .. run_end] {
+        element.store(value, Ordering::Relaxed);
+      }
+      index = run_end;
+      run += 1;
+    }
+  });
+}
+
+fn finish_fill(workers: &Workers, task: *mut TaskObject<Data>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
@@ -0,0 +1,89 @@
+use core::sync::atomic::{Ordering, AtomicU64, AtomicUsize};
+use crate::cases::compact::chained::{ BlockInfo, adaptive_scan, block_count, block_range, reset };
+use crate::cases::dedup::{count_unique_sequential_by, previous_value};
+use crate::cases::rle::encode_starts_sequential;
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+
+// Run-length encoding in two phases.
+// The first phase is an adaptive chained scan like dedup::our_chained, which writes the first value of each run to 'values',
+// and the index where the run starts to 'lengths'.
+// The second phase converts those start indices to the lengths of the runs.
+// output_count is set to the number of runs.
+pub fn create_task(input: &[u64], temp: &[BlockInfo], values: &[AtomicU64], lengths: &[AtomicU64], output_count: &AtomicUsize) -> Task {
+  reset(temp);
+  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, values, lengths, output_count, count: 0 }, block_count(input.len()), false)
+}
+
+struct Data<'a> {
+  input: &'a [u64],
+  temp: &'a [BlockInfo],
+  values: &'a [AtomicU64],
+  lengths: &'a [AtomicU64],
+  output_count: &'a AtomicUsize,
+  // The number of runs, known after the first phase.
+  count: usize
+}
+
+fn equal(a: &u64, b: &u64) -> bool {
+  a == b
+}
+
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      count_unique_sequential_by(&equal, &data.input[start .. end], previous_value(data.input, start))
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      encode_starts_sequential(&data.input[start .. end], start, previous_value(data.input, start), data.values, data.lengths, prefix)
+    }
+  );
+}
+
+fn finish(workers: &Workers, task: *mut TaskObject<Data>) {
+  let mut data = unsafe { TaskObject::take_data(task) };
+  data.count = data.temp.last().map_or(0, |block| block.prefix.load(Ordering::Relaxed));
+  data.output_count.store(data.count, Ordering::Relaxed);
+
+  if data.count == 0 || workers.is_cancelled() {
+    workers.finish();
+    return;
+  }
+
+  // The length of a run is the start of the next run minus its own start.
+  // To convert the starts in place, a block of runs must know the start of the first run after that block
+  // before the next block overwrites it. We store those in the aggregate fields of temp, which are not used anymore.
+  // As there are at most as many runs as elements, temp has enough blocks.
+  let block_count = block_count(data.count);
+  for block_index in 0 .. block_count {
+    let (_, end) = block_range(data.count, block_index);
+    let next = if end == data.count { data.input.len() as u64 } else { data.lengths[end].load(Ordering::Relaxed) };
+    data.temp[block_index as usize].aggregate.store(next as usize, Ordering::Relaxed);
+  }
+
+  let mut task = Task::new_dataparallel::<Data>(run_lengths, finish_lengths, data, block_count, false);
+  workers.inherit_continuation(&mut task);
+  workers.push_task(task);
+}
+
+fn run_lengths(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  workassisting_loop!(loop_arguments, |block_index| {
+    let (start, end) = block_range(data.count, block_index);
+    let mut next = data.temp[block_index as usize].aggregate.load(Ordering::Relaxed) as u64;
+    for length in data.lengths[start .. end].iter().rev() {
+      let run_start = length.load(Ordering::Relaxed);
+      length.store(next - run_start, Ordering::Relaxed);
+      next = run_start;
+    }
+  });
+}
+
+fn finish_lengths(workers: &Workers, task: *mut TaskObject<Data>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
@@ -2,28 +2,39 @@ use core::sync::atomic::{Ordering, AtomicU64};
 use num_format::{Locale, ToFormattedString};
 use crate::core::worker::*;
 use crate::utils;
-use crate::utils::benchmark::{benchmark, ChartStyle};
+use crate::utils::benchmark::{benchmark, ChartStyle, RUNS};
+use crate::utils::global_constants::{THREAD_COUNTS, MAX_THREADS};
+use crate::utils::perf::measure_hit_rate;
+use crate::utils::stores::Stores;
+use half_sized_blocks::Prefetch;
 
 mod unchanged_half_sized;
-mod chained;
-mod our_chained;
+pub mod chained;
+pub mod our_chained;
 mod half_sized_blocks;
 mod our_half_sized_blocks;
 mod half_sized_variant;
 mod no_lookback_chained;
+mod our_chained_epoch;
+mod our_chained_packed;
+mod our_two_sided;
+mod simd;
 
 
 pub const SIZE: usize = 1024 * 1024 * 64;
 
 pub fn run(cpp_enabled: bool) {
   for size in [SIZE] {
-    let temp = chained::create_temp();
+    let temp = chained::create_temp(size);
     let no_lookback_temp = no_lookback_chained::create_temp();
+    let packed_temp = our_chained_packed::create_temp();
+    let padded_temp = chained::create_padded_temp(size);
     let half_sized_temp = half_sized_blocks::create_temp(); //new temp for half_sized_blocks
     
-    let input = unsafe { utils::array::alloc_undef_u64_array(size) };
-    let output = unsafe { utils::array::alloc_undef_u64_array(size) };
-    fill(&input);
+    let input = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+    let output = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+    fill_parallel(&input);
+    touch_parallel(&output);
     let name = "Prefix-sum (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
     benchmark(
         ChartStyle::WithKey,
@@ -66,6 +77,103 @@ pub fn run(cpp_enabled: bool) {
         Workers::run(thread_count, task);
         compute_output(&output)
       })
+      .parallel("Adaptive chained scan (epochs)", 10, None, true, || {}, |thread_count| {
+        let task = our_chained_epoch::init_single(&input, &temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive chained scan (packed)", 11, None, true, || {}, |thread_count| {
+        let task = our_chained_packed::init_single(&input, &packed_temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Chained scan (padded)", 12, None, false, || {}, |thread_count| {
+        let task = chained::init_single(&input, &padded_temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive chained scan (padded)", 13, None, true, || {}, |thread_count| {
+        let task = our_chained::init_single(&input, &padded_temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive chained scan (streaming stores)", 14, None, true, || {}, |thread_count| {
+        let task = our_chained::init_single_with_stores(&input, &temp, &output, Stores::for_output(&input, &output));
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Half-sized variant (prefetch unfinished)", 15, None, false, || {}, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::UNFINISHED);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Half-sized variant (prefetch next block)", 18, None, false, || {}, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::NEXT_BLOCK);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Half-sized variant (prefetch both)", 20, None, false, || {}, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::BOTH);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Our Half-sized blocks (prefetch unfinished)", 16, None, true, || {}, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::UNFINISHED);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Our Half-sized blocks (prefetch next block)", 19, None, true, || {}, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::NEXT_BLOCK);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Our Half-sized blocks (prefetch both)", 21, None, true, || {}, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&input, &half_sized_temp, &output, Prefetch::BOTH);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive two-sided scan", 17, None, true, || {}, |thread_count| {
+        let task = our_two_sided::init_single(&input, &temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .cpp_sequential(cpp_enabled, "Reference C++", "scan-sequential", size)
+      .cpp_tbb(cpp_enabled, "oneTBB", 1, None, "scan-tbb", size)
+      .cpp_parlay(cpp_enabled, "ParlayLib", 2, None, "scan-parlay", size);
+  }
+}
+
+// Compares regular and streaming stores for the output, on arrays that are much larger than the last level cache.
+pub fn run_streaming(cpp_enabled: bool) {
+  for size in [SIZE, SIZE * 4] {
+    let temp = chained::create_temp(size);
+
+    let input = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+    let output = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+    fill_parallel(&input);
+    touch_parallel(&output);
+    let name = "Prefix-sum streaming stores (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+    benchmark(
+        ChartStyle::WithKey,
+        &name,
+        || {},
+        || { reference_sequential_single(&input, &output) }
+      )
+      .parallel("Chained scan", 4, None, false, || {}, |thread_count| {
+        let task = chained::init_single(&input, &temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+        let task = our_chained::init_single(&input, &temp, &output);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
+      .parallel("Adaptive chained scan (streaming stores)", 14, None, true, || {}, |thread_count| {
+        let task = our_chained::init_single_with_stores(&input, &temp, &output, Stores::Streaming);
+        Workers::run(thread_count, task);
+        compute_output(&output)
+      })
       .cpp_sequential(cpp_enabled, "Reference C++", "scan-sequential", size)
       .cpp_tbb(cpp_enabled, "oneTBB", 1, None, "scan-tbb", size)
       .cpp_parlay(cpp_enabled, "ParlayLib", 2, None, "scan-parlay", size);
@@ -74,11 +182,14 @@ pub fn run(cpp_enabled: bool) {
 
 pub fn run_inplace(cpp_enabled: bool) {
   for size in [SIZE] {
-    let temp = chained::create_temp();
+    let temp = chained::create_temp(size);
     let no_lookback_temp = no_lookback_chained::create_temp();
+    let packed_temp = our_chained_packed::create_temp();
+    let padded_temp = chained::create_padded_temp(size);
     let half_sized_temp = half_sized_blocks::create_temp();
 
-    let values = unsafe { utils::array::alloc_undef_u64_array(size) };
+    let values = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+    touch_parallel(&values);
     let name = "Prefix-sum inplace (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
     benchmark(
         if size < SIZE { ChartStyle::WithKey } else { ChartStyle::WithoutKey },
@@ -121,18 +232,122 @@ pub fn run_inplace(cpp_enabled: bool) {
         Workers::run(thread_count, task);
         compute_output(&values)
       })
+      .parallel("Adaptive chained scan (epochs)", 10, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_chained_epoch::init_single(&values, &temp, &values);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Adaptive chained scan (packed)", 11, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_chained_packed::init_single(&values, &packed_temp, &values);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Chained scan (padded)", 12, None, false, || { fill(&values) }, |thread_count| {
+        let task = chained::init_single(&values, &padded_temp, &values);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Adaptive chained scan (padded)", 13, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_chained::init_single(&values, &padded_temp, &values);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Half-sized variant (prefetch unfinished)", 15, None, false, || { fill(&values) }, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::UNFINISHED);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Half-sized variant (prefetch next block)", 18, None, false, || { fill(&values) }, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::NEXT_BLOCK);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Half-sized variant (prefetch both)", 20, None, false, || { fill(&values) }, |thread_count| {
+        let task = half_sized_variant::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::BOTH);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Our Half-sized blocks (prefetch unfinished)", 16, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::UNFINISHED);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Our Half-sized blocks (prefetch next block)", 19, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::NEXT_BLOCK);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
+      .parallel("Our Half-sized blocks (prefetch both)", 21, None, true, || { fill(&values) }, |thread_count| {
+        let task = our_half_sized_blocks::init_single_with_prefetch(&values, &half_sized_temp, &values, Prefetch::BOTH);
+        Workers::run(thread_count, task);
+        compute_output(&values)
+      })
       .cpp_sequential(cpp_enabled, "Reference C++", "scan-inplace-sequential", size)
       .cpp_tbb(cpp_enabled, "oneTBB", 1, None, "scan-inplace-tbb", size)
       .cpp_parlay(cpp_enabled, "ParlayLib", 2, None, "scan-inplace-parlay", size);
   }
 }
 
+// Compares the hit rate of the last level cache of the half-sized variants, with each combination of the prefetch hints.
+// The speedups of these variants are measured in run and run_inplace.
+pub fn run_prefetch() {
+  let size = SIZE;
+  let half_sized_temp = half_sized_blocks::create_temp();
+  let input = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+  let output = unsafe { utils::array::alloc_undef_u64_array_huge(size) };
+  fill_parallel(&input);
+  touch_parallel(&output);
+  let thread_count = largest_thread_count();
+
+  let variants = [("", Prefetch::NONE), (" (prefetch unfinished)", Prefetch::UNFINISHED), (" (prefetch next block)", Prefetch::NEXT_BLOCK), (" (prefetch both)", Prefetch::BOTH)];
+
+  println!();
+  println!("Cache hit rates of prefix-sum (n = {}, {} threads)", size.to_formatted_string(&Locale::en), thread_count);
+  for (name, prefetch) in variants {
+    print_hit_rate(&("Half-sized variant".to_owned() + name), || {
+      Workers::run(thread_count, half_sized_variant::init_single_with_prefetch(&input, &half_sized_temp, &output, prefetch));
+    });
+  }
+  for (name, prefetch) in variants {
+    print_hit_rate(&("Our Half-sized blocks".to_owned() + name), || {
+      Workers::run(thread_count, our_half_sized_blocks::init_single_with_prefetch(&input, &half_sized_temp, &output, prefetch));
+    });
+  }
+}
+
+fn print_hit_rate<F: FnMut()>(name: &str, mut f: F) {
+  let hit_rate = measure_hit_rate(|| {
+    for _ in 0 .. RUNS {
+      f();
+    }
+  });
+  match hit_rate {
+    Some(hit_rate) => println!("{:45} {:.2}%", name, hit_rate * 100.0),
+    None => println!("{:45} unavailable", name)
+  }
+}
+
 pub fn fill(values: &[AtomicU64]) {
   for (idx, value) in values.iter().enumerate() {
     value.store(random(idx as u64) as u64, Ordering::Relaxed);
   }
 }
 
+// Fills the array on the worker threads, such that its pages are distributed over the NUMA nodes.
+pub fn fill_parallel(values: &[AtomicU64]) {
+  utils::array::fill_parallel(largest_thread_count(), values, |idx| random(idx) as u64);
+}
+
+// Touches the pages of an output array on the worker threads, before the sequential reference would place them all on one node.
+pub fn touch_parallel(values: &[AtomicU64]) {
+  utils::array::fill_parallel(largest_thread_count(), values, |_| 0);
+}
+
+// The largest thread count that the benchmarks use. This does not exceed the length of AFFINITY_MAPPING.
+fn largest_thread_count() -> usize {
+  THREAD_COUNTS.into_iter().rev().find(|&thread_count| thread_count <= MAX_THREADS as usize).unwrap()
+}
+
 pub fn compute_output(output: &[AtomicU64]) -> u64 {
   output[0].load(Ordering::Relaxed) + output[98238].load(Ordering::Relaxed) + output[output.len() - 123].load(Ordering::Relaxed) + output[output.len() - 1].load(Ordering::Relaxed)
 }
@@ -143,21 +358,18 @@ pub fn reference_sequential_single(input: &[AtomicU64], output: &[AtomicU64]) ->
 }
 
 pub fn scan_sequential(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
-  let mut accumulator = initial;
-  assert_eq!(input.len(), output.len());
-  for i in 0 .. output.len() {
-    accumulator += input[i].load(Ordering::Relaxed);
-    output[i].store(accumulator, Ordering::Relaxed);
+  simd::scan(input, initial, output)
+}
+
+pub fn scan_sequential_with_stores(input: &[AtomicU64], initial: u64, output: &[AtomicU64], stores: Stores) -> u64 {
+  match stores {
+    Stores::Regular => simd::scan(input, initial, output),
+    Stores::Streaming => simd::scan_streaming(input, initial, output)
   }
-  accumulator
 }
 
 pub fn fold_sequential(array: &[AtomicU64]) -> u64 {
-  let mut accumulator = 0;
-  for value in array {
-    accumulator += value.load(Ordering::Relaxed);
-  }
-  accumulator
+  simd::fold(array)
 }
 
 fn random(mut seed: u64) -> u32 {
@@ -46,6 +46,24 @@ pub struct BlockInfo {
   pub prefix: AtomicU64
 }
 
+// The software prefetch hints of the half-sized variants (half_sized_variant and our_half_sized_blocks).
+// Both hints can be toggled separately, such that run_prefetch can measure their effects independently.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Prefetch {
+  // Prefetch the next block into L2 while reducing the current block.
+  // Blocks are claimed from a shared counter, hence this thread only processes that block if no other thread claims it first.
+  pub next_block: bool,
+  // Prefetch the unfinished block into L1 before its lookback, as it may be evicted by the time it is rescanned.
+  pub unfinished: bool
+}
+
+impl Prefetch {
+  pub const NONE: Prefetch = Prefetch{ next_block: false, unfinished: false };
+  pub const NEXT_BLOCK: Prefetch = Prefetch{ next_block: true, unfinished: false };
+  pub const UNFINISHED: Prefetch = Prefetch{ next_block: false, unfinished: true };
+  pub const BOTH: Prefetch = Prefetch{ next_block: true, unfinished: true };
+}
+
 pub const STATE_INITIALIZED: u64 = 0;
 pub const STATE_AGGREGATE_AVAILABLE: u64 = 1;
 pub const STATE_PREFIX_AVAILABLE: u64 = 2;
@@ -62,7 +80,7 @@ fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) ->
 }
 
 // Step 2: run the task
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) }; // get the data from the task (unsafe is spooky)
   
   // Update this after every loop
@@ -90,7 +108,7 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
 
       // Check if it has an unfinished block
       if let Some(u_index) = unfinished_index {
-        process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+        process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
       }
 
       // Replace unfinished block with current block
@@ -104,12 +122,12 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
 
   // Perform last unfinished block
   if let Some(u_index) = unfinished_index {
-    process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+    process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
   }
 }
 
 #[inline(always)]
-fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
+fn process_unfinished_block(workers: &Workers, data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
   // Find aggregate
   let mut aggregate = 0;
   let mut previous = u_index - 1;
@@ -122,6 +140,9 @@ fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize,
     } else if previous_state == STATE_AGGREGATE_AVAILABLE {
       aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
       previous = previous - 1;
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      break;
     } else {
       // Continue looping until the state of previous block changes.
     }
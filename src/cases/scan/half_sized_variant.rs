@@ -4,35 +4,42 @@ use crate::cases::scan::scan_sequential;
 use crate::core::worker::*;
 use crate::core::task::*;
 use crate::core::workassisting_loop::*;
-use crate::cases::scan::half_sized_blocks::{ BLOCK_SIZE, BlockInfo, reset, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE};
+use crate::utils::prefetch::{prefetch, Locality};
+use crate::cases::scan::half_sized_blocks::{ BLOCK_SIZE, BlockInfo, reset, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE, Prefetch };
 
 pub const SIZE: usize = crate::cases::scan::SIZE;
 
 // Step 0: reset the atomics
 pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  init_single_with_prefetch(input, temp, output, Prefetch::NONE)
+}
+
+// See Prefetch for the hints that can be enabled.
+pub fn init_single_with_prefetch(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64], prefetch: Prefetch) -> Task {
   reset(temp);
-  create_task(input, temp, output)
+  create_task(input, temp, output, prefetch)
 }
 
 struct Data<'a> {
   input: &'a [AtomicU64],
   temp: &'a [BlockInfo],
-  output: &'a [AtomicU64]
+  output: &'a [AtomicU64],
+  prefetch: Prefetch
 }
 
 // Step 1: initialize the task
-fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64], prefetch: Prefetch) -> Task {
   Task::new_dataparallel::<Data>(
     run, 
     finish, 
-    Data{ input, temp, output },
+    Data{ input, temp, output, prefetch },
     // is it not just temp.len(), always in every case?
     ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, 
     false) // for "our" solution
 }
 
 // Step 2: run the task
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) }; // get the data from the task (unsafe is spooky)
   
   // Update this after every loop
@@ -52,6 +59,10 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
       data.temp[block_index as usize].prefix.store(local, Ordering::Relaxed);
       data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
     } else {
+      if data.prefetch.next_block && end < data.input.len() {
+        // This thread may claim the next block soon. Fetch it into L2 while we reduce the current block.
+        prefetch(&data.input[end .. (end + BLOCK_SIZE as usize).min(data.input.len())], Locality::L2);
+      }
       let local = fold_sequential(&data.input[start .. end]);
       // Share own local value
       data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
@@ -87,7 +98,7 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
       {
         // Need to make room for new unfinished block
         if let Some(u_index) = unfinished_index {
-          process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+          process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
         }
 
         unfinished_index = Some(block_index);
@@ -99,13 +110,18 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
   });
 
   if let Some(u_index) = unfinished_index {
-    process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+    process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
   }
 
 }
 
 #[inline(always)]
-fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
+fn process_unfinished_block(workers: &Workers, data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
+  if data.prefetch.unfinished {
+    // The block is loaded while we perform the lookback, such that the rescan hits the cache.
+    prefetch(&data.input[unfinished_start .. unfinished_end], Locality::L1);
+  }
+
   // Find aggregate
   let mut aggregate = 0;
   let mut previous = u_index - 1;
@@ -118,6 +134,9 @@ fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize,
     } else if previous_state == STATE_AGGREGATE_AVAILABLE {
       aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
       previous = previous - 1;
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      break;
     } else {
       // Continue looping until the state of previous block changes.
     }
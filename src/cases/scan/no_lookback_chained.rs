@@ -45,7 +45,7 @@ fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) ->
   Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   workassisting_loop!(loop_arguments, |block_index| {
     // reduce-then-scan
@@ -68,6 +68,10 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
         if previous_state == STATE_PREFIX_AVAILABLE {
           prefix = data.temp[previous as usize].prefix.load(Ordering::Acquire);
           break;
+        } else if workers.is_cancelled() {
+          // The task is cancelled, hence the previous block may never be published.
+          prefix = 0;
+          break;
         } else {
           // Continue looping until the state of previous block changes.
         }
@@ -1,29 +1,38 @@
+use core::borrow::Borrow;
 use core::sync::atomic::{Ordering, AtomicU64};
 use crate::cases::scan::fold_sequential;
-use crate::cases::scan::scan_sequential;
+use crate::cases::scan::scan_sequential_with_stores;
 use crate::cases::scan::chained::{ BlockInfo, reset, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE };
 use crate::core::worker::*;
 use crate::core::task::*;
 use crate::core::workassisting_loop::*;
+use crate::utils::lookback_wait::LookbackWait;
+use crate::utils::stores::Stores;
 
 const BLOCK_SIZE: u64 = 1024 * 4;
 
-pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+pub fn init_single<B: Borrow<BlockInfo> + Sync>(input: &[AtomicU64], temp: &[B], output: &[AtomicU64]) -> Task {
+  init_single_with_stores(input, temp, output, Stores::Regular)
+}
+
+pub fn init_single_with_stores<B: Borrow<BlockInfo> + Sync>(input: &[AtomicU64], temp: &[B], output: &[AtomicU64], stores: Stores) -> Task {
   reset(temp);
-  create_task(input, temp, output)
+  create_task(input, temp, output, stores)
 }
 
-struct Data<'a> {
+struct Data<'a, B> {
   input: &'a [AtomicU64],
-  temp: &'a [BlockInfo],
-  output: &'a [AtomicU64]
+  temp: &'a [B],
+  output: &'a [AtomicU64],
+  inplace: bool,
+  stores: Stores
 }
 
-fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
-  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
+fn create_task<B: Borrow<BlockInfo> + Sync>(input: &[AtomicU64], temp: &[B], output: &[AtomicU64], stores: Stores) -> Task {
+  Task::new_dataparallel::<Data<B>>(run::<B>, finish::<B>, Data{ input, temp, output, inplace: core::ptr::eq(input.as_ptr(), output.as_ptr()), stores }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run<B: Borrow<BlockInfo>>(workers: &Workers, task: *const TaskObject<Data<B>>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   let mut sequential = true;
   workassisting_loop!(loop_arguments, |block_index| {
@@ -40,52 +49,73 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
       Some(0)
     } else {
       let previous = block_index - 1;
-      let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+      let previous_state = data.temp[previous as usize].borrow().state.load(Ordering::Acquire);
       if previous_state == STATE_PREFIX_AVAILABLE {
-        Some(data.temp[previous as usize].prefix.load(Ordering::Acquire))
+        Some(data.temp[previous as usize].borrow().prefix.load(Ordering::Acquire))
       } else {
         None
       }
     };
 
     if let Some(aggregate) = aggregate_start {
-      let local = scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
-      data.temp[block_index as usize].prefix.store(local, Ordering::Relaxed);
-      data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+      let local = scan_sequential_with_stores(&data.input[start .. end], aggregate, &data.output[start .. end], data.stores);
+      data.temp[block_index as usize].borrow().prefix.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].borrow().state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
     } else {
       sequential = false;
       let local = fold_sequential(&data.input[start .. end]);
       // Share own local value
-      data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
-      data.temp[block_index as usize].state.store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
+      data.temp[block_index as usize].borrow().aggregate.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].borrow().state.store(STATE_AGGREGATE_AVAILABLE, Ordering::Release);
 
       // Find aggregate
       let mut aggregate = 0;
       let mut previous = block_index - 1;
+      let mut wait = LookbackWait::new();
 
       loop {
-        let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+        let previous_state = data.temp[previous as usize].borrow().state.load(Ordering::Acquire);
         if previous_state == STATE_PREFIX_AVAILABLE {
-          aggregate = data.temp[previous as usize].prefix.load(Ordering::Acquire) + aggregate;
+          aggregate = data.temp[previous as usize].borrow().prefix.load(Ordering::Acquire) + aggregate;
           break;
         } else if previous_state == STATE_AGGREGATE_AVAILABLE {
-          aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
+          aggregate = data.temp[previous as usize].borrow().aggregate.load(Ordering::Acquire) + aggregate;
           previous = previous - 1;
-        } else {
+          wait.reset();
+        } else if workers.is_cancelled() {
+          // The task is cancelled, hence the previous block may never be published.
+          break;
+        } else if !wait.expired() {
           // Continue looping until the state of previous block changes.
+        } else if data.inplace {
+          // The thread working on the previous block may be descheduled.
+          // As this scan is in-place, that block may already be partially overwritten,
+          // so we cannot reduce it ourselves. Instead we yield to give that thread a chance to progress.
+          std::thread::yield_now();
+        } else {
+          // The thread working on the previous block may be descheduled.
+          // Instead of waiting for it, we reduce that block ourselves.
+          let previous_start = previous as usize * BLOCK_SIZE as usize;
+          let previous_end = previous_start + BLOCK_SIZE as usize;
+          aggregate += fold_sequential(&data.input[previous_start .. previous_end]);
+          if previous == 0 {
+            break;
+          }
+          previous -= 1;
+          wait.reset();
         }
       }
 
       // Make aggregate available
-      data.temp[block_index as usize].prefix.store(aggregate + local, Ordering::Relaxed);
-      data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
+      data.temp[block_index as usize].borrow().prefix.store(aggregate + local, Ordering::Relaxed);
+      data.temp[block_index as usize].borrow().state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
 
-      scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
+      scan_sequential_with_stores(&data.input[start .. end], aggregate, &data.output[start .. end], data.stores);
     }
   });
 }
 
-fn finish(workers: &Workers, task: *mut TaskObject<Data>) {
+fn finish<B>(workers: &Workers, task: *mut TaskObject<Data<B>>) {
   let _ = unsafe { TaskObject::take_data(task) };
   workers.finish();
 }
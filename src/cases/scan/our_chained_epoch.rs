@@ -0,0 +1,100 @@
+use core::sync::atomic::{Ordering, AtomicU64};
+use crate::cases::scan::fold_sequential;
+use crate::cases::scan::scan_sequential;
+use crate::cases::scan::chained::{ BlockInfo, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+use crate::utils::epoch::{ next_epoch, tagged_state };
+
+const BLOCK_SIZE: u64 = 1024 * 4;
+
+// Variant of our_chained which does not reset temp.
+// The states in temp are tagged with an epoch, states of previous scans are thus ignored.
+pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  create_task(input, temp, output, next_epoch())
+}
+
+struct Data<'a> {
+  input: &'a [AtomicU64],
+  temp: &'a [BlockInfo],
+  output: &'a [AtomicU64],
+  epoch: u64
+}
+
+fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64], epoch: u64) -> Task {
+  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output, epoch }, (input.len() as u64).div_ceil(BLOCK_SIZE) as u32, false)
+}
+
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let prefix_available = tagged_state(data.epoch, STATE_PREFIX_AVAILABLE);
+  let aggregate_available = tagged_state(data.epoch, STATE_AGGREGATE_AVAILABLE);
+  let mut sequential = true;
+  workassisting_loop!(loop_arguments, |block_index| {
+    // reduce-then-scan
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.input.len());
+
+    // Check if we already have an aggregate of the previous block.
+    // If that is the case, then we can perform the scan directly.
+    // Otherwise we perform a reduce-then-scan over this block.
+    let aggregate_start = if !sequential {
+      None // Don't switch back from parallel mode to sequential mode
+    } else if block_index ==  0 {
+      Some(0)
+    } else {
+      let previous = block_index - 1;
+      let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+      if previous_state == prefix_available {
+        Some(data.temp[previous as usize].prefix.load(Ordering::Acquire))
+      } else {
+        None
+      }
+    };
+
+    if let Some(aggregate) = aggregate_start {
+      let local = scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
+      data.temp[block_index as usize].prefix.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(prefix_available, Ordering::Release);
+    } else {
+      sequential = false;
+      let local = fold_sequential(&data.input[start .. end]);
+      // Share own local value
+      data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(aggregate_available, Ordering::Release);
+
+      // Find aggregate
+      let mut aggregate = 0;
+      let mut previous = block_index - 1;
+
+      loop {
+        let previous_state = data.temp[previous as usize].state.load(Ordering::Acquire);
+        if previous_state == prefix_available {
+          aggregate += data.temp[previous as usize].prefix.load(Ordering::Acquire);
+          break;
+        } else if previous_state == aggregate_available {
+          aggregate += data.temp[previous as usize].aggregate.load(Ordering::Acquire);
+          previous -= 1;
+        } else if workers.is_cancelled() {
+          // The task is cancelled, hence the previous block may never be published.
+          break;
+        } else {
+          // Continue looping until the state of previous block changes.
+          // This includes states of an older epoch.
+        }
+      }
+
+      // Make aggregate available
+      data.temp[block_index as usize].prefix.store(aggregate + local, Ordering::Relaxed);
+      data.temp[block_index as usize].state.store(prefix_available, Ordering::Release);
+
+      scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
+    }
+  });
+}
+
+fn finish(workers: &Workers, task: *mut TaskObject<Data>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
@@ -0,0 +1,128 @@
+use core::sync::atomic::{Ordering, AtomicU64};
+use crate::cases::scan::fold_sequential;
+use crate::cases::scan::scan_sequential;
+use crate::cases::scan::chained::{ STATE_INITIALIZED, STATE_AGGREGATE_AVAILABLE, STATE_PREFIX_AVAILABLE };
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+
+pub const SIZE: usize = crate::cases::scan::SIZE;
+const BLOCK_SIZE: u64 = 1024 * 4;
+
+// The state and the value (aggregate or prefix) of a block are packed in a single word.
+// The lower FLAG_BITS bits contain the state, the remaining 62 bits contain the value.
+// Hence the lookback only needs one load per predecessor, instead of a load of the state followed by a load of the value.
+// This requires that all prefixes fit in 62 bits, which holds for inputs of 32-bit values with less than 2^30 elements.
+// pack asserts this; the scan panics on inputs with larger prefixes.
+pub struct BlockInfo {
+  pub descriptor: AtomicU64
+}
+
+const FLAG_BITS: u32 = 2;
+const FLAG_MASK: u64 = (1 << FLAG_BITS) - 1;
+
+pub fn create_temp() -> Box<[BlockInfo]> {
+  (0 .. (SIZE as u64).div_ceil(BLOCK_SIZE)).map(|_| BlockInfo{
+    descriptor: AtomicU64::new(STATE_INITIALIZED)
+  }).collect()
+}
+
+pub fn reset(temp: &[BlockInfo]) {
+  for block in temp {
+    block.descriptor.store(STATE_INITIALIZED, Ordering::Relaxed);
+  }
+}
+
+pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  reset(temp);
+  create_task(input, temp, output)
+}
+
+#[inline(always)]
+fn pack(state: u64, value: u64) -> u64 {
+  // Checked in release builds too, as a larger value would be truncated silently and corrupt the output.
+  // This is one comparison per block, which is negligible compared to the scan of the block.
+  assert_eq!(value >> (64 - FLAG_BITS), 0, "Value does not fit in a packed descriptor");
+  (value << FLAG_BITS) | state
+}
+
+#[inline(always)]
+fn unpack(descriptor: u64) -> (u64, u64) {
+  (descriptor & FLAG_MASK, descriptor >> FLAG_BITS)
+}
+
+struct Data<'a> {
+  input: &'a [AtomicU64],
+  temp: &'a [BlockInfo],
+  output: &'a [AtomicU64]
+}
+
+fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output }, (input.len() as u64).div_ceil(BLOCK_SIZE) as u32, false)
+}
+
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let mut sequential = true;
+  workassisting_loop!(loop_arguments, |block_index| {
+    // reduce-then-scan
+    let start = block_index as usize * BLOCK_SIZE as usize;
+    let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.input.len());
+
+    // Check if we already have an aggregate of the previous block.
+    // If that is the case, then we can perform the scan directly.
+    // Otherwise we perform a reduce-then-scan over this block.
+    let aggregate_start = if !sequential {
+      None // Don't switch back from parallel mode to sequential mode
+    } else if block_index ==  0 {
+      Some(0)
+    } else {
+      let (previous_state, previous_value) = unpack(data.temp[block_index as usize - 1].descriptor.load(Ordering::Acquire));
+      if previous_state == STATE_PREFIX_AVAILABLE {
+        Some(previous_value)
+      } else {
+        None
+      }
+    };
+
+    if let Some(aggregate) = aggregate_start {
+      let local = scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
+      data.temp[block_index as usize].descriptor.store(pack(STATE_PREFIX_AVAILABLE, local), Ordering::Release);
+    } else {
+      sequential = false;
+      let local = fold_sequential(&data.input[start .. end]);
+      // Share own local value
+      data.temp[block_index as usize].descriptor.store(pack(STATE_AGGREGATE_AVAILABLE, local), Ordering::Release);
+
+      // Find aggregate
+      let mut aggregate = 0;
+      let mut previous = block_index - 1;
+
+      loop {
+        let (previous_state, previous_value) = unpack(data.temp[previous as usize].descriptor.load(Ordering::Acquire));
+        if previous_state == STATE_PREFIX_AVAILABLE {
+          aggregate += previous_value;
+          break;
+        } else if previous_state == STATE_AGGREGATE_AVAILABLE {
+          aggregate += previous_value;
+          previous -= 1;
+        } else if workers.is_cancelled() {
+          // The task is cancelled, hence the previous block may never be published.
+          break;
+        } else {
+          // Continue looping until the state of previous block changes.
+        }
+      }
+
+      // Make aggregate available
+      data.temp[block_index as usize].descriptor.store(pack(STATE_PREFIX_AVAILABLE, aggregate + local), Ordering::Release);
+
+      scan_sequential(&data.input[start .. end], aggregate, &data.output[start .. end]);
+    }
+  });
+}
+
+fn finish(workers: &Workers, task: *mut TaskObject<Data>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
@@ -1,28 +1,35 @@
 use core::sync::atomic::{Ordering, AtomicU64};
 use crate::cases::scan::fold_sequential;
 use crate::cases::scan::scan_sequential;
-use crate::cases::scan::half_sized_blocks::{ BLOCK_SIZE, BlockInfo, reset, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE};
+use crate::cases::scan::half_sized_blocks::{ BLOCK_SIZE, BlockInfo, reset, STATE_PREFIX_AVAILABLE, STATE_AGGREGATE_AVAILABLE, Prefetch };
 use crate::core::worker::*;
 use crate::core::task::*;
 use crate::core::workassisting_loop::*;
+use crate::utils::prefetch::{prefetch, Locality};
 
 // with only 1 thread?
 pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  init_single_with_prefetch(input, temp, output, Prefetch::NONE)
+}
+
+// See Prefetch for the hints that can be enabled.
+pub fn init_single_with_prefetch(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64], prefetch: Prefetch) -> Task {
   reset(temp);
-  create_task(input, temp, output)
+  create_task(input, temp, output, prefetch)
 }
 
 struct Data<'a> {
   input: &'a [AtomicU64],
   temp: &'a [BlockInfo],
-  output: &'a [AtomicU64]
+  output: &'a [AtomicU64],
+  prefetch: Prefetch
 }
 
-fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
-  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
+fn create_task(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64], prefetch: Prefetch) -> Task {
+  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output, prefetch }, ((input.len() as u64 + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32, false)
 }
 
-fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+fn run(workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
   let data = unsafe { TaskObject::get_data(task) };
   let mut sequential = true;
 
@@ -62,6 +69,10 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
       data.temp[block_index as usize].state.store(STATE_PREFIX_AVAILABLE, Ordering::Release);
     } else {
       sequential = false;
+      if data.prefetch.next_block && end < data.input.len() {
+        // This thread may claim the next block soon. Fetch it into L2 while we reduce the current block.
+        prefetch(&data.input[end .. (end + BLOCK_SIZE as usize).min(data.input.len())], Locality::L2);
+      }
       let local = fold_sequential(&data.input[start .. end]);
       // Share own local value
       data.temp[block_index as usize].aggregate.store(local, Ordering::Relaxed);
@@ -69,7 +80,7 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
 
       // Check if it has an unfinished block, should only be here if not sequential
       if let Some(u_index) = unfinished_index {
-        process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+        process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
       }
       
          // Replace unfinished block with current block
@@ -83,12 +94,17 @@ fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopAr
 
   // Perform last unfinished block
   if let Some(u_index) = unfinished_index {
-    process_unfinished_block(data, u_index, unfinished_start, unfinished_end, unfinished_local);
+    process_unfinished_block(workers, data, u_index, unfinished_start, unfinished_end, unfinished_local);
   }
 }
 
 #[inline(always)]
-fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
+fn process_unfinished_block(workers: &Workers, data: &Data, u_index: u32, unfinished_start: usize, unfinished_end: usize, unfinished_local: u64) {
+  if data.prefetch.unfinished {
+    // The block is loaded while we perform the lookback, such that the rescan hits the cache.
+    prefetch(&data.input[unfinished_start .. unfinished_end], Locality::L1);
+  }
+
   // Find aggregate
   let mut aggregate = 0;
   let mut previous = u_index - 1;
@@ -101,6 +117,9 @@ fn process_unfinished_block(data: &Data, u_index: u32, unfinished_start: usize,
     } else if previous_state == STATE_AGGREGATE_AVAILABLE {
       aggregate = data.temp[previous as usize].aggregate.load(Ordering::Acquire) + aggregate;
       previous = previous - 1;
+    } else if workers.is_cancelled() {
+      // The task is cancelled, hence the previous block may never be published.
+      break;
     } else {
       // Continue looping until the state of previous block changes.
     }
@@ -0,0 +1,98 @@
+use core::sync::atomic::{Ordering, AtomicU32, AtomicU64};
+use crate::cases::scan::fold_sequential;
+use crate::cases::scan::scan_sequential;
+use crate::cases::scan::chained::BlockInfo;
+use crate::core::cancellation::CancellationToken;
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+
+const BLOCK_SIZE: u64 = 1024 * 4;
+
+// A scan where the first thread scans blocks sequentially from left to right,
+// while the other threads reduce blocks from right to left.
+// When the two meet, the other threads scan their blocks in a second phase,
+// using the aggregates from the first phase.
+// Compared to the adaptive chained scan, threads never wait on a lookback.
+// This uses the aggregate and prefix fields of the temp array, which is written before it is read,
+// hence it does not need to be reset.
+pub fn init_single(input: &[AtomicU64], temp: &[BlockInfo], output: &[AtomicU64]) -> Task {
+  let block_count = block_count(input.len());
+  Task::new_dataparallel::<Data>(run, finish, Data{ input, temp, output, sequential_count: AtomicU32::new(block_count), sequential_prefix: AtomicU64::new(0) }, block_count, true)
+}
+
+struct Data<'a> {
+  input: &'a [AtomicU64],
+  temp: &'a [BlockInfo],
+  output: &'a [AtomicU64],
+  // The number of blocks scanned by the first thread.
+  sequential_count: AtomicU32,
+  // The prefix of the last block scanned by the first thread.
+  sequential_prefix: AtomicU64
+}
+
+fn block_count(length: usize) -> u32 {
+  (length as u64).div_ceil(BLOCK_SIZE) as u32
+}
+
+fn block_range(data: &Data, block_index: u32) -> (usize, usize) {
+  let start = block_index as usize * BLOCK_SIZE as usize;
+  let end = ((block_index as usize + 1) * BLOCK_SIZE as usize).min(data.input.len());
+  (start, end)
+}
+
+fn run(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let mut accumulator = 0;
+  workassisting_loop_two_sided!(loop_arguments, |block_index| {
+    // First thread
+    let (start, end) = block_range(data, block_index);
+    accumulator = scan_sequential(&data.input[start .. end], accumulator, &data.output[start .. end]);
+    data.sequential_prefix.store(accumulator, Ordering::Relaxed);
+  }, |block_index| {
+    // Other threads
+    let (start, end) = block_range(data, block_index);
+    let aggregate = fold_sequential(&data.input[start .. end]);
+    data.temp[block_index as usize].aggregate.store(aggregate, Ordering::Relaxed);
+  }, |sequential_count, _parallel_count| {
+    data.sequential_count.store(sequential_count, Ordering::Relaxed);
+  });
+}
+
+fn finish(workers: &Workers, task: *mut TaskObject<Data>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  let block_count = block_count(data.input.len());
+  let sequential_count = data.sequential_count.load(Ordering::Relaxed);
+
+  if sequential_count == block_count || workers.is_cancelled() {
+    workers.finish();
+    return;
+  }
+
+  // Compute the prefixes of the blocks that were reduced by the other threads.
+  let mut prefix = data.sequential_prefix.load(Ordering::Relaxed);
+  for block_index in sequential_count .. block_count {
+    data.temp[block_index as usize].prefix.store(prefix, Ordering::Relaxed);
+    prefix += data.temp[block_index as usize].aggregate.load(Ordering::Relaxed);
+  }
+
+  let mut task = Task::new_dataparallel::<Data>(run_parallel, finish_parallel, data, block_count - sequential_count, false);
+  workers.inherit_continuation(&mut task);
+  workers.push_task(task);
+}
+
+fn run_parallel(_workers: &Workers, task: *const TaskObject<Data>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let sequential_count = data.sequential_count.load(Ordering::Relaxed);
+  workassisting_loop!(loop_arguments, |index| {
+    let block_index = sequential_count + index;
+    let (start, end) = block_range(data, block_index);
+    let prefix = data.temp[block_index as usize].prefix.load(Ordering::Relaxed);
+    scan_sequential(&data.input[start .. end], prefix, &data.output[start .. end]);
+  });
+}
+
+fn finish_parallel(workers: &Workers, task: *mut TaskObject<Data>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
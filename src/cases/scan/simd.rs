@@ -0,0 +1,267 @@
+// Vectorized kernels for the scan and fold within a block.
+// The loops in scan_sequential and fold_sequential load each AtomicU64 separately,
+// which prevents auto-vectorization. These kernels read the arrays via raw pointers instead.
+// This is valid as AtomicU64 has the same in-memory representation as u64,
+// and as a block is only accessed by one thread at a time.
+// The implementation is chosen at runtime, based on the features of the processor.
+use core::sync::atomic::AtomicU64;
+#[cfg(not(target_arch = "x86_64"))]
+use core::sync::atomic::Ordering;
+
+pub fn scan(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
+  scan_with::<false>(input, initial, output)
+}
+
+// Scans with non-temporal (streaming) stores for the output. See utils::stores.
+pub fn scan_streaming(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
+  scan_with::<true>(input, initial, output)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn scan_with<const STREAMING: bool>(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
+  assert_eq!(input.len(), output.len());
+  let input_ptr = input.as_ptr() as *const u64;
+  let output_ptr = output.as_ptr() as *mut u64;
+  if is_x86_feature_detected!("avx512f") {
+    unsafe { scan_avx512::<STREAMING>(input_ptr, initial, output_ptr, input.len()) }
+  } else if is_x86_feature_detected!("avx2") {
+    unsafe { scan_avx2::<STREAMING>(input_ptr, initial, output_ptr, input.len()) }
+  } else {
+    // SSE2 is always available on x86-64
+    unsafe { scan_sse2::<STREAMING>(input_ptr, initial, output_ptr, input.len()) }
+  }
+}
+
+// Non-temporal stores are not available in the portable implementation, so both variants perform regular stores.
+#[cfg(not(target_arch = "x86_64"))]
+fn scan_with<const STREAMING: bool>(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
+  scan_portable(input, initial, output)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn fold(array: &[AtomicU64]) -> u64 {
+  let pointer = array.as_ptr() as *const u64;
+  if is_x86_feature_detected!("avx512f") {
+    unsafe { fold_avx512(pointer, array.len()) }
+  } else if is_x86_feature_detected!("avx2") {
+    unsafe { fold_avx2(pointer, array.len()) }
+  } else {
+    unsafe { fold_sse2(pointer, array.len()) }
+  }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn fold(array: &[AtomicU64]) -> u64 {
+  fold_portable(array)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn scan_portable(input: &[AtomicU64], initial: u64, output: &[AtomicU64]) -> u64 {
+  let mut accumulator = initial;
+  assert_eq!(input.len(), output.len());
+  for i in 0 .. output.len() {
+    accumulator += input[i].load(Ordering::Relaxed);
+    output[i].store(accumulator, Ordering::Relaxed);
+  }
+  accumulator
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn fold_portable(array: &[AtomicU64]) -> u64 {
+  let mut accumulator = 0;
+  for value in array {
+    accumulator += value.load(Ordering::Relaxed);
+  }
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn store<const STREAMING: bool>(pointer: *mut u64, value: u64) {
+  if STREAMING {
+    _mm_stream_si64(pointer as *mut i64, value as i64);
+  } else {
+    *pointer = value;
+  }
+}
+
+// Streaming stores of a vector require an aligned address.
+// Returns the number of elements to process one by one, before output is aligned to 'alignment' bytes.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn unaligned_head<const STREAMING: bool>(output: *mut u64, alignment: usize, length: usize) -> usize {
+  if STREAMING {
+    output.align_offset(alignment).min(length)
+  } else {
+    0
+  }
+}
+
+// Non-temporal stores are weakly ordered. The fence assures that they are visible to other threads
+// before the state of the block is published (with a release store).
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn fence<const STREAMING: bool>() {
+  if STREAMING {
+    _mm_sfence();
+  }
+}
+
+// Scans the elements in [start, length) one by one. Used for the remaining elements after the vectorized loop.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn scan_remainder<const STREAMING: bool>(input: *const u64, mut accumulator: u64, output: *mut u64, start: usize, length: usize) -> u64 {
+  for i in start .. length {
+    accumulator = accumulator.wrapping_add(*input.add(i));
+    store::<STREAMING>(output.add(i), accumulator);
+  }
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+unsafe fn fold_remainder(input: *const u64, mut accumulator: u64, start: usize, length: usize) -> u64 {
+  for i in start .. length {
+    accumulator = accumulator.wrapping_add(*input.add(i));
+  }
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn scan_avx512<const STREAMING: bool>(input: *const u64, initial: u64, output: *mut u64, length: usize) -> u64 {
+  let head = unaligned_head::<STREAMING>(output, 64, length);
+  let initial = scan_remainder::<STREAMING>(input, initial, output, 0, head);
+  let zero = _mm512_setzero_si512();
+  let last_lane = _mm512_set1_epi64(7);
+  let mut carry = _mm512_set1_epi64(initial as i64);
+  let mut i = head;
+  while i + 8 <= length {
+    let mut x = _mm512_loadu_si512(input.add(i) as *const _);
+    // Scan within the vector, by adding the vector shifted by 1, 2 and 4 lanes.
+    x = _mm512_add_epi64(x, _mm512_alignr_epi64::<7>(x, zero));
+    x = _mm512_add_epi64(x, _mm512_alignr_epi64::<6>(x, zero));
+    x = _mm512_add_epi64(x, _mm512_alignr_epi64::<4>(x, zero));
+    x = _mm512_add_epi64(x, carry);
+    if STREAMING {
+      _mm512_stream_si512(output.add(i) as *mut _, x);
+    } else {
+      _mm512_storeu_si512(output.add(i) as *mut _, x);
+    }
+    // Broadcast the last lane, to add to the next vector.
+    carry = _mm512_permutexvar_epi64(last_lane, x);
+    i += 8;
+  }
+  let accumulator = _mm_cvtsi128_si64(_mm512_castsi512_si128(carry)) as u64;
+  let accumulator = scan_remainder::<STREAMING>(input, accumulator, output, i, length);
+  fence::<STREAMING>();
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn scan_avx2<const STREAMING: bool>(input: *const u64, initial: u64, output: *mut u64, length: usize) -> u64 {
+  let head = unaligned_head::<STREAMING>(output, 32, length);
+  let initial = scan_remainder::<STREAMING>(input, initial, output, 0, head);
+  let zero = _mm256_setzero_si256();
+  let mut carry = _mm256_set1_epi64x(initial as i64);
+  let mut i = head;
+  while i + 4 <= length {
+    let mut x = _mm256_loadu_si256(input.add(i) as *const __m256i);
+    // [a, b, c, d] + [0, a, b, c] = [a, a + b, b + c, c + d]
+    x = _mm256_add_epi64(x, _mm256_blend_epi32::<0b0000_0011>(_mm256_permute4x64_epi64::<0b10_01_00_00>(x), zero));
+    // [a, a + b, b + c, c + d] + [0, 0, a, a + b] = [a, a + b, a + b + c, a + b + c + d]
+    x = _mm256_add_epi64(x, _mm256_blend_epi32::<0b0000_1111>(_mm256_permute4x64_epi64::<0b01_00_00_00>(x), zero));
+    x = _mm256_add_epi64(x, carry);
+    if STREAMING {
+      _mm256_stream_si256(output.add(i) as *mut __m256i, x);
+    } else {
+      _mm256_storeu_si256(output.add(i) as *mut __m256i, x);
+    }
+    // Broadcast the last lane, to add to the next vector.
+    carry = _mm256_permute4x64_epi64::<0b11_11_11_11>(x);
+    i += 4;
+  }
+  let accumulator = _mm_cvtsi128_si64(_mm256_castsi256_si128(carry)) as u64;
+  let accumulator = scan_remainder::<STREAMING>(input, accumulator, output, i, length);
+  fence::<STREAMING>();
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn scan_sse2<const STREAMING: bool>(input: *const u64, initial: u64, output: *mut u64, length: usize) -> u64 {
+  let head = unaligned_head::<STREAMING>(output, 16, length);
+  let initial = scan_remainder::<STREAMING>(input, initial, output, 0, head);
+  let mut carry = _mm_set1_epi64x(initial as i64);
+  let mut i = head;
+  while i + 2 <= length {
+    let mut x = _mm_loadu_si128(input.add(i) as *const __m128i);
+    // [a, b] + [0, a] = [a, a + b]
+    x = _mm_add_epi64(x, _mm_slli_si128::<8>(x));
+    x = _mm_add_epi64(x, carry);
+    if STREAMING {
+      _mm_stream_si128(output.add(i) as *mut __m128i, x);
+    } else {
+      _mm_storeu_si128(output.add(i) as *mut __m128i, x);
+    }
+    carry = _mm_unpackhi_epi64(x, x);
+    i += 2;
+  }
+  let accumulator = _mm_cvtsi128_si64(carry) as u64;
+  let accumulator = scan_remainder::<STREAMING>(input, accumulator, output, i, length);
+  fence::<STREAMING>();
+  accumulator
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn fold_avx512(input: *const u64, length: usize) -> u64 {
+  // Two accumulators, to hide the latency of the additions.
+  let mut accumulator_1 = _mm512_setzero_si512();
+  let mut accumulator_2 = _mm512_setzero_si512();
+  let mut i = 0;
+  while i + 16 <= length {
+    accumulator_1 = _mm512_add_epi64(accumulator_1, _mm512_loadu_si512(input.add(i) as *const _));
+    accumulator_2 = _mm512_add_epi64(accumulator_2, _mm512_loadu_si512(input.add(i + 8) as *const _));
+    i += 16;
+  }
+  let accumulator = _mm512_reduce_add_epi64(_mm512_add_epi64(accumulator_1, accumulator_2)) as u64;
+  fold_remainder(input, accumulator, i, length)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn fold_avx2(input: *const u64, length: usize) -> u64 {
+  let mut accumulator_1 = _mm256_setzero_si256();
+  let mut accumulator_2 = _mm256_setzero_si256();
+  let mut i = 0;
+  while i + 8 <= length {
+    accumulator_1 = _mm256_add_epi64(accumulator_1, _mm256_loadu_si256(input.add(i) as *const __m256i));
+    accumulator_2 = _mm256_add_epi64(accumulator_2, _mm256_loadu_si256(input.add(i + 4) as *const __m256i));
+    i += 8;
+  }
+  let sum = _mm256_add_epi64(accumulator_1, accumulator_2);
+  let sum = _mm_add_epi64(_mm256_castsi256_si128(sum), _mm256_extracti128_si256::<1>(sum));
+  let accumulator = _mm_cvtsi128_si64(_mm_add_epi64(sum, _mm_unpackhi_epi64(sum, sum))) as u64;
+  fold_remainder(input, accumulator, i, length)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn fold_sse2(input: *const u64, length: usize) -> u64 {
+  let mut accumulator_1 = _mm_setzero_si128();
+  let mut accumulator_2 = _mm_setzero_si128();
+  let mut i = 0;
+  while i + 4 <= length {
+    accumulator_1 = _mm_add_epi64(accumulator_1, _mm_loadu_si128(input.add(i) as *const __m128i));
+    accumulator_2 = _mm_add_epi64(accumulator_2, _mm_loadu_si128(input.add(i + 2) as *const __m128i));
+    i += 4;
+  }
+  let sum = _mm_add_epi64(accumulator_1, accumulator_2);
+  let accumulator = _mm_cvtsi128_si64(_mm_add_epi64(sum, _mm_unpackhi_epi64(sum, sum))) as u64;
+  fold_remainder(input, accumulator, i, length)
+}
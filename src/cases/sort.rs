@@ -0,0 +1,103 @@
+use core::cell::RefCell;
+use core::sync::atomic::AtomicUsize;
+use num_format::{Locale, ToFormattedString};
+use crate::cases::compact;
+use crate::core::worker::*;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+
+mod merge_sort;
+mod radix_sort;
+
+pub const SIZE: usize = 1024 * 1024 * 8;
+
+pub fn run(cpp_enabled: bool) {
+  let size = SIZE;
+  let input = create_input(size);
+  let values = RefCell::new(vec![0; size].into_boxed_slice());
+  let buffer = RefCell::new(vec![0; size].into_boxed_slice());
+  let histogram_temp = compact::chained::create_histogram_temp::<{ radix_sort::BUCKETS }>(size);
+  let bucket_offsets: Box<[AtomicUsize]> = (0 .. radix_sort::BUCKETS).map(|_| AtomicUsize::new(0)).collect();
+
+  let name = "Sort (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || { values.borrow_mut().copy_from_slice(&input) },
+      || { reference_sequential_single(&mut values.borrow_mut()) }
+    )
+    .parallel("Merge sort (join)", 7, None, true, || { values.borrow_mut().copy_from_slice(&input) }, |thread_count| {
+      let mut values = values.borrow_mut();
+      let task = merge_sort::create_task(&mut values, &mut buffer.borrow_mut());
+      Workers::run(thread_count, task);
+      compute_output(&values)
+    })
+    .parallel("Radix sort (LSD)", 8, None, true, || { values.borrow_mut().copy_from_slice(&input) }, |thread_count| {
+      let mut values = values.borrow_mut();
+      let mut buffer = buffer.borrow_mut();
+      let graph = radix_sort::create_graph(&mut values, &mut buffer, &histogram_temp, &bucket_offsets);
+      graph.run(thread_count);
+      compute_output(&values)
+    })
+    .cpp_sequential(cpp_enabled, "Reference C++", "sort-sequential", size)
+    .cpp_tbb(cpp_enabled, "oneTBB", 1, None, "sort-tbb", size)
+    .cpp_parlay(cpp_enabled, "ParlayLib", 2, None, "sort-parlay", size);
+}
+
+pub fn create_input(size: usize) -> Box<[u64]> {
+  (0 .. size).map(|x| random(x as u64)).collect()
+}
+
+pub fn compute_output(values: &[u64]) -> (u64, u64, u64, u64) {
+  (values[0], values[98238], values[values.len() - 123], values[values.len() - 1])
+}
+
+pub fn reference_sequential_single(values: &mut [u64]) -> (u64, u64, u64, u64) {
+  values.sort_unstable();
+  compute_output(values)
+}
+
+// Matches randomize_64 in reference-cpp/common.h, such that the C++ sorts get the same input.
+fn random(mut seed: u64) -> u64 {
+  seed ^= seed << 13;
+  seed ^= seed >> 7;
+  seed ^= seed << 17;
+  seed
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::AtomicUsize;
+  use crate::cases::compact;
+  use crate::cases::sort::*;
+  use crate::utils::testing::thread_counts;
+
+  // Sorts the input with the parallel radix sort, for each thread count,
+  // and checks that this gives the same result as sort.
+  fn radix_sort(input: &[u64]) {
+    let mut expected = input.to_vec();
+    expected.sort();
+    let temp = compact::chained::create_histogram_temp::<{ radix_sort::BUCKETS }>(input.len());
+    let bucket_offsets: Box<[AtomicUsize]> = (0 .. radix_sort::BUCKETS).map(|_| AtomicUsize::new(0)).collect();
+    for thread_count in thread_counts() {
+      let mut values = input.to_vec();
+      let mut buffer = vec![0; input.len()];
+      radix_sort::create_graph(&mut values, &mut buffer, &temp, &bucket_offsets).run(thread_count);
+      assert_eq!(values, expected);
+    }
+  }
+
+  #[test]
+  fn sizes() {
+    let block_size = compact::chained::BLOCK_SIZE as usize;
+    for size in [0, 1, block_size - 1, block_size, block_size + 1, 100_003] {
+      radix_sort(&create_input(size));
+    }
+  }
+
+  #[test]
+  fn duplicates() {
+    let input: Vec<u64> = create_input(20_000).iter().map(|value| value % 100).collect();
+    radix_sort(&input);
+    radix_sort(&[u64::MAX, 0, u64::MAX, 1 << 63, 0, 255, 256]);
+  }
+}
@@ -0,0 +1,94 @@
+use crate::core::worker::*;
+use crate::core::task::*;
+
+// Arrays with at most this many elements are sorted or merged sequentially.
+const SEQUENTIAL_SORT_SIZE: usize = 1024 * 16;
+const SEQUENTIAL_MERGE_SIZE: usize = 1024 * 16;
+
+// Sorts values, using buffer as temporary storage. Both arrays should have the same length.
+pub fn create_task<'a>(values: &'a mut [u64], buffer: &'a mut [u64]) -> Task {
+  assert_eq!(values.len(), buffer.len());
+  Task::new_single::<Data>(run, Data{ values, buffer })
+}
+
+struct Data<'a> {
+  values: &'a mut [u64],
+  buffer: &'a mut [u64]
+}
+
+fn run(workers: &Workers, task: *mut TaskObject<Data>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  sort(workers, data.values, data.buffer, false);
+  workers.finish();
+}
+
+// Sorts values. The result is written to buffer if into_buffer is set, and to values otherwise.
+// The halves are sorted into the other array, such that the merge can write to the destination directly.
+fn sort(workers: &Workers, values: &mut [u64], buffer: &mut [u64], into_buffer: bool) {
+  if values.len() <= SEQUENTIAL_SORT_SIZE {
+    values.sort();
+    if into_buffer {
+      buffer.copy_from_slice(values);
+    }
+    return;
+  }
+
+  let middle = values.len() / 2;
+  {
+    let (values_left, values_right) = values.split_at_mut(middle);
+    let (buffer_left, buffer_right) = buffer.split_at_mut(middle);
+    workers.join(
+      |workers| sort(workers, values_left, buffer_left, !into_buffer),
+      |workers| sort(workers, values_right, buffer_right, !into_buffer)
+    );
+  }
+
+  if into_buffer {
+    let (left, right) = values.split_at(middle);
+    merge(workers, left, right, buffer);
+  } else {
+    let (left, right) = buffer.split_at(middle);
+    merge(workers, left, right, values);
+  }
+}
+
+// Merges the sorted arrays left and right into output.
+// We split the larger array in the middle, and split the other array at the same value using a binary search.
+// The two pairs of halves are then merged in parallel.
+// Equal values of left are placed before those of right, hence this merge is stable.
+fn merge(workers: &Workers, left: &[u64], right: &[u64], output: &mut [u64]) {
+  if left.len() + right.len() <= SEQUENTIAL_MERGE_SIZE {
+    merge_sequential(left, right, output);
+    return;
+  }
+
+  let (left_split, right_split) = if left.len() >= right.len() {
+    let left_split = left.len() / 2;
+    let pivot = left[left_split];
+    (left_split, right.partition_point(|&value| value < pivot))
+  } else {
+    let right_split = right.len() / 2;
+    let pivot = right[right_split];
+    (left.partition_point(|&value| value <= pivot), right_split)
+  };
+
+  let (output_first, output_second) = output.split_at_mut(left_split + right_split);
+  workers.join(
+    |workers| merge(workers, &left[.. left_split], &right[.. right_split], output_first),
+    |workers| merge(workers, &left[left_split ..], &right[right_split ..], output_second)
+  );
+}
+
+fn merge_sequential(left: &[u64], right: &[u64], output: &mut [u64]) {
+  let mut i = 0;
+  let mut j = 0;
+  for slot in output.iter_mut() {
+    if j == right.len() || (i < left.len() && left[i] <= right[j]) {
+      *slot = left[i];
+      i += 1;
+    } else {
+      *slot = right[j];
+      j += 1;
+    }
+  }
+}
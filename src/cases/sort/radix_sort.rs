@@ -0,0 +1,38 @@
+use core::sync::atomic::AtomicUsize;
+use crate::cases::compact::chained::HistogramBlockInfo;
+use crate::cases::split;
+use crate::core::graph::TaskGraph;
+use crate::utils::shared_slice::SharedSlice;
+
+// Bits per digit
+const DIGIT_BITS: usize = 8;
+pub const BUCKETS: usize = 1 << DIGIT_BITS;
+const PASSES: usize = u64::BITS as usize / DIGIT_BITS;
+const _: () = assert!(PASSES.is_multiple_of(2), "The result of an odd number of passes would end up in the buffer");
+
+// The key functions of the passes, from the least significant digit to the most significant digit.
+const DIGITS: [fn(&u64) -> usize; PASSES] = [digit::<0>, digit::<1>, digit::<2>, digit::<3>, digit::<4>, digit::<5>, digit::<6>, digit::<7>];
+
+fn digit<const PASS: usize>(value: &u64) -> usize {
+  ((value >> (PASS * DIGIT_BITS)) as usize) & (BUCKETS - 1)
+}
+
+// LSD radix sort. Each pass is a stable k-way split on one digit, from values to buffer or back.
+// The passes run as a chain in a task graph, such that the threads continue with the next pass directly.
+// As the number of passes is even, the result ends up in values.
+pub fn create_graph<'a>(values: &'a mut [u64], buffer: &'a mut [u64], temp: &'a [HistogramBlockInfo<BUCKETS>], bucket_offsets: &'a [AtomicUsize]) -> TaskGraph<'a> {
+  assert_eq!(values.len(), buffer.len());
+  let values = SharedSlice::new(values);
+  let buffer = SharedSlice::new(buffer);
+
+  let mut graph = TaskGraph::new();
+  let mut previous = None;
+  for (pass, key) in DIGITS.iter().enumerate() {
+    let (input, output) = if pass % 2 == 0 { (values, buffer) } else { (buffer, values) };
+    let dependencies: &[_] = match &previous { Some(node) => core::slice::from_ref(node), None => &[] };
+    // The previous pass has finished when this task is created, hence its output is not written anymore.
+    let node = graph.add(move || split::our_chained::create_task::<u64, _, BUCKETS>(key, unsafe { input.as_slice() }, temp, output, bucket_offsets), dependencies);
+    previous = Some(node);
+  }
+  graph
+}
@@ -0,0 +1,121 @@
+use core::cell::RefCell;
+use core::sync::atomic::{Ordering, AtomicUsize};
+use num_format::{Locale, ToFormattedString};
+use crate::cases::compact;
+use crate::core::worker::*;
+use crate::utils::benchmark::{benchmark, ChartStyle};
+use crate::utils::shared_slice::SharedSlice;
+
+pub mod our_chained;
+
+pub const SIZE: usize = 1024 * 1024 * 32;
+pub const BUCKETS: usize = 256;
+
+// A stable k-way split, which scatters the elements into buckets by a key function.
+// This is the building block of a radix sort.
+pub fn run() {
+  let size = SIZE;
+  let input = compact::create_input(size);
+  let temp = compact::chained::create_histogram_temp::<BUCKETS>(size);
+  let output = RefCell::new(vec![0; size].into_boxed_slice());
+  let bucket_offsets: Box<[AtomicUsize]> = (0 .. BUCKETS).map(|_| AtomicUsize::new(0)).collect();
+  let key = |value: &u64| (*value as usize) % BUCKETS;
+
+  let name = "Split (n = ".to_owned() + &(size).to_formatted_string(&Locale::en) + ", k = " + &BUCKETS.to_string() + ")";
+  benchmark(
+      ChartStyle::WithKey,
+      &name,
+      || {},
+      || reference_sequential_single::<u64, _, BUCKETS>(&key, &input, &mut output.borrow_mut())
+    )
+    .parallel("Adaptive chained scan", 7, None, true, || {}, |thread_count| {
+      let mut output = output.borrow_mut();
+      let task = our_chained::create_task::<u64, _, BUCKETS>(&key, &input, &temp, SharedSlice::new(&mut output), &bucket_offsets);
+      Workers::run(thread_count, task);
+      let offsets: Vec<usize> = bucket_offsets.iter().map(|offset| offset.load(Ordering::Relaxed)).collect();
+      compute_output(&offsets, &output)
+    });
+}
+
+pub fn compute_output<T: Copy>(bucket_offsets: &[usize], output: &[T]) -> (usize, usize, T, T, T) {
+  (bucket_offsets[1], bucket_offsets[bucket_offsets.len() - 1], output[0], output[98238], output[output.len() - 1])
+}
+
+pub fn reference_sequential_single<T: Copy, F: Fn(&T) -> usize, const K: usize>(key: &F, input: &[T], output: &mut [T]) -> (usize, usize, T, T, T) {
+  let mut histogram = [0; K];
+  histogram_sequential(key, input, &mut histogram);
+  let bucket_offsets = exclusive_scan(histogram);
+  let mut offsets = bucket_offsets;
+  split_sequential(key, input, &SharedSlice::new(output), &mut offsets);
+  compute_output(&bucket_offsets, output)
+}
+
+// Adds the number of elements per bucket to the histogram.
+pub fn histogram_sequential<T, F: Fn(&T) -> usize, const K: usize>(key: &F, input: &[T], histogram: &mut [usize; K]) {
+  for value in input {
+    histogram[key(value)] += 1;
+  }
+}
+
+// Writes each element of the input to the output at the offset of its bucket, and increments that offset.
+// The caller must assure that no other thread writes to those parts of the output concurrently.
+pub fn split_sequential<T: Clone, F: Fn(&T) -> usize, const K: usize>(key: &F, input: &[T], output: &SharedSlice<T>, offsets: &mut [usize; K]) {
+  for value in input {
+    let bucket = key(value);
+    unsafe { output.write(offsets[bucket], value.clone()) };
+    offsets[bucket] += 1;
+  }
+}
+
+pub fn exclusive_scan<const K: usize>(counts: [usize; K]) -> [usize; K] {
+  let mut accumulator = 0;
+  counts.map(|count| {
+    let offset = accumulator;
+    accumulator += count;
+    offset
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicUsize};
+  use crate::cases::compact;
+  use crate::cases::split::*;
+  use crate::utils::testing::thread_counts;
+
+  // Splits the input with the parallel implementation, for each thread count,
+  // and checks that this gives the same result as a stable sort by the key.
+  fn split<F: Fn(&u64) -> usize + Sync, const K: usize>(input: &[u64], key: F) {
+    let mut expected = input.to_vec();
+    expected.sort_by_key(&key);
+    let mut histogram = [0; K];
+    histogram_sequential(&key, input, &mut histogram);
+    let expected_offsets = exclusive_scan(histogram);
+
+    let temp = compact::chained::create_histogram_temp::<K>(input.len());
+    for thread_count in thread_counts() {
+      let mut output = vec![u64::MAX; input.len()];
+      let bucket_offsets: Box<[AtomicUsize]> = (0 .. K).map(|_| AtomicUsize::new(usize::MAX)).collect();
+      Workers::run(thread_count, our_chained::create_task::<u64, _, K>(&key, input, &temp, SharedSlice::new(&mut output), &bucket_offsets));
+      let offsets: Vec<usize> = bucket_offsets.iter().map(|offset| offset.load(Ordering::Relaxed)).collect();
+      assert_eq!(offsets, expected_offsets);
+      assert_eq!(output, expected);
+    }
+  }
+
+  #[test]
+  fn sizes() {
+    let block_size = compact::chained::BLOCK_SIZE as usize;
+    for size in [0, 1, block_size - 1, block_size, block_size + 1, 100_003] {
+      let input = compact::create_input(size);
+      split::<_, 16>(&input, |value| (*value as usize) % 16);
+      split::<_, BUCKETS>(&input, |value| (*value as usize >> 8) % BUCKETS);
+    }
+  }
+
+  #[test]
+  fn single_bucket() {
+    let input = compact::create_input(3 * compact::chained::BLOCK_SIZE as usize + 5);
+    split::<_, 4>(&input, |_| 2);
+  }
+}
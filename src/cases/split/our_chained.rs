@@ -0,0 +1,90 @@
+use core::sync::atomic::{Ordering, AtomicUsize};
+use crate::cases::compact::chained::{ ChainedBlock, HistogramBlockInfo, adaptive_scan, block_count, block_range, reset_histogram };
+use crate::cases::split::{exclusive_scan, histogram_sequential, split_sequential};
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
+use crate::utils::shared_slice::SharedSlice;
+
+// A stable k-way split in two phases.
+// The first phase counts the number of elements per bucket, to compute the offsets of the buckets.
+// The second phase is an adaptive chained scan like compact::our_chained, where the descriptors of the blocks
+// have a counter per bucket. The prefix of a block and the offsets of the buckets give the positions
+// of the elements in the output.
+// bucket_offsets is set to the start of each bucket in the output.
+pub fn create_task<T: Clone + Send + Sync, F: Fn(&T) -> usize + Sync, const K: usize>(key: &F, input: &[T], temp: &[HistogramBlockInfo<K>], output: SharedSlice<T>, bucket_offsets: &[AtomicUsize]) -> Task {
+  assert_eq!(bucket_offsets.len(), K);
+  reset_histogram(temp);
+  for offset in bucket_offsets {
+    offset.store(0, Ordering::Relaxed);
+  }
+  Task::new_dataparallel::<Data<T, F, K>>(run_histogram::<T, F, K>, finish_histogram::<T, F, K>, Data{ key, input, temp, output, bucket_offsets }, block_count(input.len()), false)
+}
+
+struct Data<'a, T, F, const K: usize> {
+  key: &'a F,
+  input: &'a [T],
+  temp: &'a [HistogramBlockInfo<K>],
+  output: SharedSlice<'a, T>,
+  bucket_offsets: &'a [AtomicUsize]
+}
+
+fn run_histogram<T, F: Fn(&T) -> usize, const K: usize>(_workers: &Workers, task: *const TaskObject<Data<T, F, K>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  // Count locally, such that we only touch the shared counters once per thread.
+  let mut histogram = [0; K];
+  workassisting_loop!(loop_arguments, |block_index| {
+    let (start, end) = block_range(data.input.len(), block_index);
+    histogram_sequential(data.key, &data.input[start .. end], &mut histogram);
+  });
+  for (total, count) in data.bucket_offsets.iter().zip(histogram) {
+    if count != 0 {
+      total.fetch_add(count, Ordering::Relaxed);
+    }
+  }
+}
+
+fn finish_histogram<T: Clone + Send + Sync, F: Fn(&T) -> usize + Sync, const K: usize>(workers: &Workers, task: *mut TaskObject<Data<T, F, K>>) {
+  let data = unsafe { TaskObject::take_data(task) };
+  // Convert the totals to the offsets of the buckets.
+  let histogram: [usize; K] = core::array::from_fn(|bucket| data.bucket_offsets[bucket].load(Ordering::Relaxed));
+  for (offset, value) in data.bucket_offsets.iter().zip(exclusive_scan(histogram)) {
+    offset.store(value, Ordering::Relaxed);
+  }
+
+  if workers.is_cancelled() {
+    workers.finish();
+    return;
+  }
+
+  let block_count = block_count(data.input.len());
+  let mut task = Task::new_dataparallel::<Data<T, F, K>>(run_scatter::<T, F, K>, finish_scatter::<T, F, K>, data, block_count, false);
+  workers.inherit_continuation(&mut task);
+  workers.push_task(task);
+}
+
+fn run_scatter<T: Clone, F: Fn(&T) -> usize, const K: usize>(workers: &Workers, task: *const TaskObject<Data<T, F, K>>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  let bucket_offsets: [usize; K] = core::array::from_fn(|bucket| data.bucket_offsets[bucket].load(Ordering::Relaxed));
+  adaptive_scan(workers, data.temp, loop_arguments,
+    |block_index| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      let mut histogram = [0; K];
+      histogram_sequential(data.key, &data.input[start .. end], &mut histogram);
+      histogram
+    },
+    |block_index, prefix| {
+      let (start, end) = block_range(data.input.len(), block_index);
+      // The prefix counts the elements per bucket before this block. Combined with the offsets of the buckets,
+      // this gives the positions of the first elements of this block per bucket.
+      let mut offsets = HistogramBlockInfo::<K>::combine(bucket_offsets, prefix);
+      split_sequential(data.key, &data.input[start .. end], &data.output, &mut offsets);
+      core::array::from_fn(|bucket| offsets[bucket] - bucket_offsets[bucket])
+    }
+  );
+}
+
+fn finish_scatter<T, F, const K: usize>(workers: &Workers, task: *mut TaskObject<Data<T, F, K>>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
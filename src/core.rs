@@ -1,3 +1,6 @@
+pub mod cancellation;
+pub mod graph;
 pub mod task;
+pub mod topology;
 pub mod workassisting_loop;
 pub mod worker;
@@ -0,0 +1,34 @@
+use core::sync::atomic::{Ordering, AtomicBool};
+
+// A token to cooperatively cancel the tasks in Workers::run_cancellable.
+// After cancellation, data-parallel loops stop claiming new blocks and
+// lookbacks stop waiting on blocks that may never be published.
+// Blocks that are already in progress are completed. As a lookback that stops early publishes an incorrect prefix,
+// the output of a cancelled task is undefined.
+pub struct CancellationToken {
+  cancelled: AtomicBool
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Cancelled;
+
+impl CancellationToken {
+  pub fn new() -> CancellationToken {
+    CancellationToken{ cancelled: AtomicBool::new(false) }
+  }
+
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  #[inline(always)]
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+}
+
+impl Default for CancellationToken {
+  fn default() -> CancellationToken {
+    CancellationToken::new()
+  }
+}
@@ -0,0 +1,103 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::core::task::*;
+use crate::core::worker::*;
+
+// A directed acyclic graph of tasks. A node is started when all its dependencies are finished,
+// and the Workers stop when all nodes are finished.
+// Tasks are created lazily, when their dependencies are finished, such that they can use the results of those dependencies.
+// For instance, the size of the output of a compact is only known when that task is finished.
+//
+// The finish function of a task in a graph should call workers.finish() as usual.
+// A task that continues in a next phase, by pushing a new task from its finish function,
+// should call workers.inherit_continuation on that new task.
+pub struct TaskGraph<'a> {
+  nodes: Vec<Node<'a>>,
+  // The number of nodes that are not finished yet.
+  remaining: AtomicUsize
+}
+
+struct Node<'a> {
+  create: Mutex<Option<Box<dyn FnOnce() -> Task + Send + 'a>>>,
+  successors: Vec<usize>,
+  // The number of dependencies that are not finished yet.
+  pending: AtomicUsize
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+impl<'a> TaskGraph<'a> {
+  pub fn new() -> Self {
+    TaskGraph{ nodes: Vec::new(), remaining: AtomicUsize::new(0) }
+  }
+
+  // Adds a node, which is started when all nodes in 'dependencies' are finished.
+  // As nodes can only depend on earlier nodes, the graph cannot contain cycles.
+  pub fn add<F: FnOnce() -> Task + Send + 'a>(&mut self, create: F, dependencies: &[NodeId]) -> NodeId {
+    let index = self.nodes.len();
+    for dependency in dependencies {
+      self.nodes[dependency.0].successors.push(index);
+    }
+    self.nodes.push(Node{
+      create: Mutex::new(Some(Box::new(create))),
+      successors: Vec::new(),
+      pending: AtomicUsize::new(dependencies.len())
+    });
+    NodeId(index)
+  }
+
+  pub fn run(self, worker_count: usize) {
+    self.remaining.store(self.nodes.len(), Ordering::Relaxed);
+    Workers::run(worker_count, Task::new_single(start, GraphPointer(&self)));
+  }
+
+  fn start_node(&self, workers: &Workers, index: usize) {
+    let create = self.nodes[index].create.lock().unwrap().take().expect("Node of TaskGraph is started twice");
+    let mut task = create();
+    task.set_continuation(Continuation{
+      function: node_finished,
+      data: self as *const TaskGraph as *const (),
+      index
+    });
+    workers.push_task(task);
+  }
+}
+
+impl<'a> Default for TaskGraph<'a> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// The graph lives on the stack of the thread that calls TaskGraph::run, until all workers are finished.
+#[derive(Copy, Clone)]
+struct GraphPointer<'a>(*const TaskGraph<'a>);
+unsafe impl Send for GraphPointer<'_> {}
+unsafe impl Sync for GraphPointer<'_> {}
+
+fn start(workers: &Workers, task: *mut TaskObject<GraphPointer>) {
+  let graph = unsafe { &*TaskObject::take_data(task).0 };
+  if graph.nodes.is_empty() {
+    workers.finish();
+    return;
+  }
+  for (index, node) in graph.nodes.iter().enumerate() {
+    if node.pending.load(Ordering::Relaxed) == 0 {
+      graph.start_node(workers, index);
+    }
+  }
+}
+
+fn node_finished(workers: &Workers, data: *const (), index: usize) {
+  let graph = unsafe { &*(data as *const TaskGraph) };
+  for &successor in &graph.nodes[index].successors {
+    if graph.nodes[successor].pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+      graph.start_node(workers, successor);
+    }
+  }
+  if graph.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+    // The continuation of this task is already taken, hence this stops the Workers.
+    workers.finish();
+  }
+}
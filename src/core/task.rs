@@ -1,7 +1,9 @@
 use core::fmt::Debug;
-use core::sync::atomic::{ AtomicI32, AtomicU32 };
+use core::sync::atomic::{ AtomicI32, AtomicU64, Ordering };
 use core::mem::forget;
 use core::ops::{Drop, Deref, DerefMut};
+use crate::core::cancellation::CancellationToken;
+use crate::core::topology::topology;
 use crate::core::worker::*;
 
 pub struct Task (*mut TaskObject<()>);
@@ -12,6 +14,9 @@ pub struct TaskObject<T> {
   pub(super) work: Option<fn(workers: &Workers, this: *const TaskObject<T>, loop_arguments: LoopArguments) -> ()>,
   // 'finish' takes ownership of the TaskObject
   pub(super) finish: fn(workers: &Workers, this: *mut TaskObject<T>) -> (),
+  // 'deallocate' takes ownership of the TaskObject and drops it, without calling finish.
+  // This is used for tasks that are not run to completion, for instance after a panic.
+  pub(super) deallocate: fn(this: *mut TaskObject<T>) -> (),
   // The number of active_threads, offset by the tag in the activities array.
   // If this task is present in activities, then:
   //   - active_threads contains - (the number of finished threads), thus non-positive.
@@ -23,12 +28,55 @@ pub struct TaskObject<T> {
   //   - no thread is still working on this task.
   // Hence we can run the finish function and deallocate the task.
   pub(super) active_threads: AtomicI32,
-  pub(super) work_index: AtomicU32,
+  // For two-sided tasks, the lower 32 bits count the blocks claimed from the left by the first thread,
+  // and the upper 32 bits count the blocks claimed from the right by the other threads.
+  pub(super) work_index: AtomicU64,
   pub(super) work_size: u32,
   pub(super) work_two_sided: bool,
+  // For node-local tasks, the range of blocks of each NUMA node. See Task::new_dataparallel_node_local.
+  pub(super) node_ranges: Option<Box<[NodeRange]>>,
+  // Called when the finish function of this task calls workers.finish(), instead of stopping the Workers.
+  // See TaskGraph.
+  pub(super) continuation: Option<Continuation>,
   pub data: T,
 }
 
+// The blocks of a node-local task that belong to one NUMA node. The blocks from 'next' up to 'end' are not claimed yet.
+// Each range has its own cache line, such that threads on different nodes don't share a counter.
+#[repr(align(64))]
+pub struct NodeRange {
+  next: AtomicU64,
+  end: u64
+}
+
+// Claims a block of a node-local task, from the range of 'node' if possible, and otherwise from the ranges of the other nodes.
+// Returns None if all blocks are claimed.
+pub(super) fn claim_node_local(ranges: &[NodeRange], node: usize) -> Option<u64> {
+  for offset in 0 .. ranges.len() {
+    let range = &ranges[(node + offset) % ranges.len()];
+    // Check before incrementing, such that the counters of exhausted ranges are not incremented by every claim.
+    if range.next.load(Ordering::Relaxed) >= range.end {
+      continue;
+    }
+    let index = range.next.fetch_add(1, Ordering::Relaxed);
+    if index < range.end {
+      return Some(index);
+    }
+  }
+  None
+}
+
+// A function to run when a task is finished, with an untyped pointer to its data and an index.
+#[derive(Copy, Clone)]
+pub struct Continuation {
+  pub function: fn(workers: &Workers, data: *const (), index: usize) -> (),
+  pub data: *const (),
+  pub index: usize
+}
+
+// The data of a continuation is shared between the threads; the owner of the continuation must assure that it is Sync.
+unsafe impl Send for Continuation {}
+
 impl Debug for Task {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
     let obj = unsafe { &*self.0 };
@@ -55,15 +103,40 @@ impl Task {
     let task_box: Box<TaskObject<T>> = Box::new(TaskObject{
       work: Some(work),
       finish,
+      deallocate: TaskObject::<T>::deallocate,
       work_size,
       active_threads: AtomicI32::new(0),
-      work_index: AtomicU32::new(if work_two_sided { 0 } else { 1 }),
+      work_index: AtomicU64::new(if work_two_sided { 0 } else { 1 }),
       work_two_sided,
+      node_ranges: None,
+      continuation: None,
       data
     });
     Task(Box::into_raw(task_box) as *mut TaskObject<()>)
   }
 
+  // As new_dataparallel, but the blocks are divided in contiguous ranges over the NUMA nodes:
+  // node k owns the k-th of node_count equal parts of the blocks. Threads first claim blocks of their own node,
+  // and only then blocks of the other nodes. If the memory of the task is divided over the nodes in the same way,
+  // as utils::array::fill_parallel does, threads then mostly access memory on their own node.
+  // Blocks are not claimed in order, hence this is only suitable for tasks whose blocks are independent,
+  // and not for instance for the chained scans, whose lookback waits on earlier blocks.
+  pub fn new_dataparallel_node_local<T: Send + Sync>(
+    work: fn(workers: &Workers, data: *const TaskObject<T>, loop_arguments: LoopArguments) -> (),
+    finish: fn(workers: &Workers, data: *mut TaskObject<T>) -> (),
+    data: T,
+    work_size: u32
+  ) -> Task {
+    let node_count = topology().node_count();
+    let mut task = Task::new_dataparallel(work, finish, data, work_size, false);
+    if node_count > 1 {
+      // With a single node, the shared counter of new_dataparallel claims the blocks in the same order.
+      let part = |node: usize| (work_size as u64 * node as u64).div_ceil(node_count as u64);
+      task.node_ranges = Some((0 .. node_count).map(|node| NodeRange{ next: AtomicU64::new(part(node)), end: part(node + 1) }).collect());
+    }
+    task
+  }
+
   pub fn new_single<T: Send + Sync>(
     function: fn(workers: &Workers, data: *mut TaskObject<T>) -> (),
     data: T
@@ -71,15 +144,22 @@ impl Task {
     let task_box: Box<TaskObject<T>> = Box::new(TaskObject{
       work: None,
       finish: function,
+      deallocate: TaskObject::<T>::deallocate,
       work_size: 0,
       active_threads: AtomicI32::new(0),
-      work_index: AtomicU32::new(0),
+      work_index: AtomicU64::new(0),
       work_two_sided: false,
+      node_ranges: None,
+      continuation: None,
       data
     });
     Task(Box::into_raw(task_box) as *mut TaskObject<()>)
   }
 
+  pub fn set_continuation(&mut self, continuation: Continuation) {
+    self.continuation = Some(continuation);
+  }
+
   // The caller should assure that the object is properly deallocated.
   // This can be done by calling Task::from_raw.
   pub fn into_raw(self) -> *mut TaskObject<()> {
@@ -94,9 +174,9 @@ unsafe impl Sync for Task {}
 
 impl Drop for Task {
   fn drop(&mut self) {
-    // We cannot drop the TaskObject<T> here, as we don't know the type argument T here.
-    // We assume that the TaskObject is passed to Workers; that will handle the deallocation of the TaskObject.
-    println!("Warning: TaskObject not cleared. Make sure that all constructed Tasks are also passed to Workers.");
+    // A Task that is dropped was never started, for instance because it was still on a deque when the Workers stopped.
+    // We don't know the type argument T here, hence we use the type-erased deallocate function.
+    (self.deallocate)(self.0);
   }
 }
 
@@ -123,11 +203,30 @@ impl<T> TaskObject<T> {
   pub unsafe fn take_data<'a>(task: *mut TaskObject<T>) -> T {
     unsafe { Box::from_raw(task) }.data
   }
+
+  fn deallocate(task: *mut TaskObject<T>) {
+    drop(unsafe { Box::from_raw(task) });
+  }
 }
 
 pub struct LoopArguments<'a> {
   pub work_size: u32,
-  pub work_index: &'a AtomicU32,
+  pub work_index: &'a AtomicU64,
   pub empty_signal: EmptySignal<'a>,
-  pub first_index: u32,
+  pub first_index: u64,
+  pub cancellation: &'a CancellationToken,
+  pub node_ranges: Option<&'a [NodeRange]>,
+  // The NUMA node of this thread, as an index in node_ranges.
+  pub node: usize
+}
+
+impl<'a> LoopArguments<'a> {
+  // Claims the next block. Returns a value of at least work_size if all blocks are claimed.
+  #[inline(always)]
+  pub fn next_block(&self) -> u64 {
+    match self.node_ranges {
+      None => self.work_index.fetch_add(1, Ordering::Relaxed),
+      Some(ranges) => claim_node_local(ranges, self.node).unwrap_or(u64::MAX)
+    }
+  }
 }
@@ -0,0 +1,121 @@
+use std::fs;
+use std::sync::OnceLock;
+use crate::utils::global_constants::AFFINITY_MAPPING;
+
+// The cache and NUMA topology of the machine, read from sysfs.
+// If the information is not available (e.g. on other operating systems),
+// all cpus are considered to be in the same group, and the victim order reduces to a ring.
+pub struct Topology {
+  // For each cpu, the lowest cpu that shares its last level cache (L3).
+  l3_groups: Vec<usize>,
+  // For each cpu, its NUMA node.
+  nodes: Vec<usize>,
+  // The distinct NUMA nodes of the cpus in AFFINITY_MAPPING, in increasing order.
+  used_nodes: Vec<usize>
+}
+
+static TOPOLOGY: OnceLock<Topology> = OnceLock::new();
+
+pub fn topology() -> &'static Topology {
+  TOPOLOGY.get_or_init(Topology::read)
+}
+
+impl Topology {
+  fn read() -> Topology {
+    let cpu_count = AFFINITY_MAPPING.iter().max().map_or(0, |max| max + 1);
+    let mut l3_groups = vec![0; cpu_count];
+    let mut nodes = vec![0; cpu_count];
+
+    for (cpu, group) in l3_groups.iter_mut().enumerate() {
+      if let Some(shared) = read_l3_shared_cpus(cpu) {
+        *group = shared.into_iter().min().unwrap_or(0);
+      }
+    }
+
+    if let Ok(entries) = fs::read_dir("/sys/devices/system/node") {
+      for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(node) = name.strip_prefix("node").and_then(|node| node.parse::<usize>().ok()) else { continue };
+        let Ok(list) = fs::read_to_string(entry.path().join("cpulist")) else { continue };
+        for cpu in parse_cpu_list(&list) {
+          if cpu < cpu_count {
+            nodes[cpu] = node;
+          }
+        }
+      }
+    }
+
+    let mut used_nodes: Vec<usize> = AFFINITY_MAPPING.iter().map(|&cpu| nodes[cpu]).collect();
+    used_nodes.sort_unstable();
+    used_nodes.dedup();
+
+    Topology{ l3_groups, nodes, used_nodes }
+  }
+
+  // The number of NUMA nodes that the worker threads may run on.
+  pub fn node_count(&self) -> usize {
+    self.used_nodes.len()
+  }
+
+  // The NUMA node of a cpu, as an index below node_count.
+  pub fn node_index(&self, cpu: usize) -> usize {
+    self.used_nodes.binary_search(&self.nodes[cpu]).unwrap()
+  }
+
+  // 0 if the cpus share their L3 cache, 1 if they are on the same NUMA node, 2 otherwise.
+  pub fn distance(&self, cpu_a: usize, cpu_b: usize) -> u32 {
+    if self.l3_groups[cpu_a] == self.l3_groups[cpu_b] {
+      0
+    } else if self.nodes[cpu_a] == self.nodes[cpu_b] {
+      1
+    } else {
+      2
+    }
+  }
+}
+
+// Computes for each thread the order in which it visits other threads, to steal tasks or to assist them.
+// Threads that share the L3 cache come first, then threads on the same NUMA node, then the others.
+// Within a group we keep the ring order, where odd threads walk in the opposite direction,
+// to spread thieves over the victims.
+// The result contains worker_count - 1 victims per thread.
+pub fn victim_order(worker_count: usize) -> Box<[usize]> {
+  let topology = topology();
+  let cpus = &AFFINITY_MAPPING[.. worker_count];
+  let mut result = Vec::with_capacity(worker_count * worker_count.saturating_sub(1));
+  for (thread_index, &cpu) in cpus.iter().enumerate() {
+    let increment = if thread_index % 2 == 0 { 1 } else { worker_count - 1 };
+    let mut victims: Vec<usize> = (1 .. worker_count).map(|step| (thread_index + step * increment) % worker_count).collect();
+    // sort_by_key is stable, hence the ring order is preserved within a group.
+    victims.sort_by_key(|&other_index| topology.distance(cpu, cpus[other_index]));
+    result.extend(victims);
+  }
+  result.into_boxed_slice()
+}
+
+fn read_l3_shared_cpus(cpu: usize) -> Option<Vec<usize>> {
+  let directory = format!("/sys/devices/system/cpu/cpu{}/cache", cpu);
+  for index in 0 .. 8 {
+    let Ok(level) = fs::read_to_string(format!("{}/index{}/level", directory, index)) else { break };
+    if level.trim() == "3" {
+      let list = fs::read_to_string(format!("{}/index{}/shared_cpu_list", directory, index)).ok()?;
+      return Some(parse_cpu_list(&list));
+    }
+  }
+  None
+}
+
+// Parses a list in the format of sysfs, e.g. "0-3,8-11".
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+  let mut cpus = vec![];
+  for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+    let range = match part.split_once('-') {
+      Some((first, last)) => first.parse::<usize>().ok().zip(last.parse::<usize>().ok()),
+      None => part.parse::<usize>().ok().map(|cpu| (cpu, cpu))
+    };
+    if let Some((first, last)) = range {
+      cpus.extend(first ..= last);
+    }
+  }
+  cpus
+}
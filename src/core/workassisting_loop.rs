@@ -5,31 +5,44 @@ macro_rules! workassisting_loop {
     // Claim work
     let mut block_idx = loop_arguments.first_index;
 
-    while block_idx < loop_arguments.work_size {
-      if block_idx == loop_arguments.work_size - 1 {
-        // All work is claimed.
+    while block_idx < loop_arguments.work_size as u64 {
+      if loop_arguments.cancellation.is_cancelled() {
+        // Stop claiming blocks
+        break;
+      }
+      if loop_arguments.node_ranges.is_none() && block_idx == loop_arguments.work_size as u64 - 1 {
+        // All work is claimed. Node-local tasks don't claim the last block last, hence they only signal this after the loop.
         loop_arguments.empty_signal.task_empty();
       }
 
       // Copy block_idx to an immutable variable, such that a user of this macro cannot mutate it.
-      let $block_index: u32 = block_idx;
+      let $block_index: u32 = block_idx as u32;
       $body
 
-      block_idx = loop_arguments.work_index.fetch_add(1, Ordering::Relaxed);
+      block_idx = loop_arguments.next_block();
     }
     loop_arguments.empty_signal.task_empty();
   };
 }
 pub(crate) use workassisting_loop;
 
+// The number of claimed blocks of a two-sided task, given its work_index.
+// The lower 32 bits count the blocks claimed from the left by the first thread,
+// and the upper 32 bits count the blocks claimed from the right by the other threads.
+#[inline(always)]
+pub fn two_sided_claimed(work_index: u64) -> u64 {
+  (work_index >> 32) + (work_index & 0xFFFF_FFFF)
+}
+
 #[macro_export]
 macro_rules! workassisting_loop_two_sided {
   ($loop_arguments_expr: expr, |$block_index_1: ident| $first_thread: block, |$block_index_2: ident| $other_threads: block, |$sequential_count: ident, $parallel_count: ident| $conclude_distribution: block) => {
     // Bind inputs to variables
     let loop_arguments: LoopArguments = $loop_arguments_expr;
     let work_size: u32 = loop_arguments.work_size;
-    let work_index: &AtomicU32 = loop_arguments.work_index;
+    let work_index: &AtomicU64 = loop_arguments.work_index;
     let mut empty_signal: EmptySignal = loop_arguments.empty_signal;
+    let cancellation: &CancellationToken = loop_arguments.cancellation;
 
     let first_try = if loop_arguments.first_index == 0 {
       work_index.compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed)
@@ -37,7 +50,9 @@ macro_rules! workassisting_loop_two_sided {
       Result::Err(0)
     };
 
-    assert!(work_size < 1 << 15);
+    // The sequential and parallel counters are packed in the lower and upper halves of work_index.
+    // Each thread increments a counter at most once past work_size, hence a half stays below 2^32 if work_size is at most 2^31.
+    assert!(work_size <= 1 << 31);
 
     if first_try.is_ok() {
       // This is the first thread. This thread goes from left to right.
@@ -51,22 +66,26 @@ macro_rules! workassisting_loop_two_sided {
         $conclude_distribution
       }
       loop {
-        let $block_index_1: u32 = block_idx;
+        if cancellation.is_cancelled() {
+          empty_signal.task_empty();
+          break;
+        }
+        let $block_index_1: u32 = block_idx as u32;
         $first_thread;
 
         let index = work_index.fetch_add(1, Ordering::Relaxed);
-        let sequential_index = index & 0xFFFF;
-        let parallel_index = index >> 16;
-        let count_claimed = sequential_index + parallel_index + 1;
-        if count_claimed > work_size {
+        let sequential_index = index & 0xFFFF_FFFF;
+        let parallel_index = index >> 32;
+        let count_claimed = two_sided_claimed(index) + 1;
+        if count_claimed > work_size as u64 {
           // Everything is claimed
           empty_signal.task_empty();
           break;
-        } else if count_claimed == work_size {
+        } else if count_claimed == work_size as u64 {
           // This is the last iteration
           empty_signal.task_empty();
-          let $sequential_count: u32 = sequential_index + 1;
-          let $parallel_count: u32 = parallel_index;
+          let $sequential_count: u32 = sequential_index as u32 + 1;
+          let $parallel_count: u32 = parallel_index as u32;
           $conclude_distribution
         }
         block_idx = sequential_index;
@@ -74,22 +93,26 @@ macro_rules! workassisting_loop_two_sided {
     } else {
       // This is not the first thread. This thread goes from right to left.
       loop {
-        let index = work_index.fetch_add(1 << 16, Ordering::Relaxed);
-        let sequential_index = index & 0xFFFF;
-        let parallel_index = index >> 16;
-        let count_claimed = sequential_index + parallel_index + 1;
-        if count_claimed > work_size {
+        if cancellation.is_cancelled() {
+          empty_signal.task_empty();
+          break;
+        }
+        let index = work_index.fetch_add(1 << 32, Ordering::Relaxed);
+        let sequential_index = index & 0xFFFF_FFFF;
+        let parallel_index = index >> 32;
+        let count_claimed = two_sided_claimed(index) + 1;
+        if count_claimed > work_size as u64 {
           // Everything is claimed
           empty_signal.task_empty();
           break;
-        } else if count_claimed == work_size {
+        } else if count_claimed == work_size as u64 {
           // This is the last iteration
           empty_signal.task_empty();
-          let $sequential_count: u32 = sequential_index;
-          let $parallel_count: u32 = parallel_index + 1;
+          let $sequential_count: u32 = sequential_index as u32;
+          let $parallel_count: u32 = parallel_index as u32 + 1;
           $conclude_distribution
         }
-        let block_index = work_size - parallel_index - 1;
+        let block_index = work_size - parallel_index as u32 - 1;
         let $block_index_2: u32 = block_index;
         $other_threads
       }
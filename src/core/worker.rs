@@ -1,21 +1,72 @@
+use core::any::Any;
+use core::cell::{Cell, UnsafeCell};
 use core::sync::atomic::AtomicBool;
 use core::sync::atomic::Ordering;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::Mutex;
 use crossbeam::deque;
+use crate::core::cancellation::{CancellationToken, Cancelled};
 use crate::core::task::*;
+use crate::core::topology::{topology, victim_order};
+use crate::core::workassisting_loop::two_sided_claimed;
 use crate::utils::ptr::AtomicTaggedPtr;
 use crate::utils::ptr::TaggedPtr;
 use crate::utils::global_constants::AFFINITY_MAPPING;
 
 pub struct Workers<'a> {
   is_finished: &'a AtomicBool,
+  thread_index: usize,
+  // The NUMA node of this thread, as an index below topology().node_count().
+  node: usize,
+  // The continuation of the task whose finish function is running on this thread.
+  continuation: Cell<Option<Continuation>>,
+  // The payload of the first panic in a task, re-raised in Workers::run.
+  panic: &'a Mutex<Option<Box<dyn Any + Send>>>,
+  cancellation: &'a CancellationToken,
   worker_count: usize,
   worker: deque::Worker<Task>,
   stealers: &'a [deque::Stealer<Task>],
+  // For each thread, the other threads in the order in which it tries to steal from or assist them.
+  // See topology::victim_order.
+  victims: &'a [usize],
   activities: &'a [AtomicTaggedPtr<TaskObject<()>>]
 }
 
 impl<'a> Workers<'a> {
+  // Runs the task and its subtasks on worker_count threads.
+  // If a task panics, the pool is stopped and the panic is resumed on the calling thread.
   pub fn run(worker_count: usize, initial_task: Task) {
+    if let Err(payload) = Workers::try_run(worker_count, initial_task) {
+      resume_unwind(payload);
+    }
+  }
+
+  // As Workers::run, but returns the payload of a panic as an error instead of resuming it.
+  // After a panic, the finish functions of the running tasks are not called, as they would continue a computation
+  // whose output is incomplete. These tasks and the tasks that were not started yet are dropped instead,
+  // hence their data (and the borrows it holds) is released before this returns.
+  pub fn try_run(worker_count: usize, initial_task: Task) -> std::thread::Result<()> {
+    Workers::try_run_with_token(worker_count, initial_task, &CancellationToken::new())
+  }
+
+  // As Workers::run, but stops when the token is cancelled.
+  // Returns Err(Cancelled) if the token was cancelled during the run. The output of the tasks is then undefined:
+  // a lookback that stops because of the cancellation publishes an incorrect prefix,
+  // hence blocks may also be written at the wrong position, not only be missing.
+  // A panic in a task also cancels the token, and is resumed on the calling thread.
+  #[cfg_attr(not(test), allow(dead_code))]
+  pub fn run_cancellable(worker_count: usize, initial_task: Task, cancellation: &CancellationToken) -> Result<(), Cancelled> {
+    if let Err(payload) = Workers::try_run_with_token(worker_count, initial_task, cancellation) {
+      resume_unwind(payload);
+    }
+    if cancellation.is_cancelled() {
+      Err(Cancelled)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn try_run_with_token(worker_count: usize, initial_task: Task, cancellation: &CancellationToken) -> std::thread::Result<()> {
     let workers: Vec<deque::Worker<Task>> = (0 .. worker_count).into_iter().map(|_| deque::Worker::new_lifo()).collect();
     let stealers: Box<[deque::Stealer<Task>]> = workers.iter().map(|w| w.stealer()).collect();
 
@@ -26,6 +77,9 @@ impl<'a> Workers<'a> {
     };
 
     let is_finished = AtomicBool::new(false);
+    let panic = Mutex::new(None);
+
+    let victims = victim_order(worker_count);
 
     let full = affinity::get_thread_affinity().unwrap();
     std::thread::scope(|s| {
@@ -36,30 +90,131 @@ impl<'a> Workers<'a> {
         affinity::set_thread_affinity([AFFINITY_MAPPING[thread_index]]).unwrap();
         let workers = Workers{
           is_finished: &is_finished,
+          thread_index,
+          node: topology().node_index(AFFINITY_MAPPING[thread_index]),
+          continuation: Cell::new(None),
+          panic: &panic,
+          cancellation,
           worker_count,
           worker,
           stealers: &stealers,
+          victims: &victims,
           activities: &activities
         };
         s.spawn(move || {
-          workers.do_work(thread_index);
+          workers.do_work();
         });
       }
       affinity::set_thread_affinity(full).unwrap();
     });
+
+    match panic.into_inner().unwrap() {
+      Some(payload) => Err(payload),
+      None => Ok(())
+    }
   }
 
+  // Called by the finish function of a task, when the task and its subtasks are done.
+  // If the task has a continuation, that is run. Otherwise this stops the Workers.
   pub fn finish(&self) {
-    self.is_finished.store(true, Ordering::Release);
+    match self.continuation.take() {
+      Some(continuation) => (continuation.function)(self, continuation.data, continuation.index),
+      None => self.is_finished.store(true, Ordering::Release)
+    }
+  }
+
+  // Moves the continuation of the finishing task to 'task'.
+  // A finish function that pushes a next phase of its computation, instead of calling workers.finish(),
+  // should call this, such that the continuation runs after that next phase.
+  pub fn inherit_continuation(&self, task: &mut Task) {
+    if let Some(continuation) = self.continuation.take() {
+      task.set_continuation(continuation);
+    }
+  }
+
+  // Tasks should check this in loops that wait on other threads, such as the lookback of a chained scan,
+  // as the thread they wait for may have stopped.
+  #[inline(always)]
+  pub fn is_cancelled(&self) -> bool {
+    self.cancellation.is_cancelled()
   }
 
   pub fn push_task(&self, task: Task) {
     self.worker.push(task);
   }
 
-  fn do_work(&self, thread_index: usize) {
+  fn do_work(&self) {
+    // If a task panics, its finish function will never be called, and hence the other threads would never stop.
+    // We catch the panic here, store it to resume it in Workers::run, and stop all threads.
+    let result = catch_unwind(AssertUnwindSafe(|| self.work_loop(self.thread_index)));
+    if let Err(payload) = result {
+      self.record_panic(payload);
+    }
+    // After a panic or cancellation, tasks may remain on our deque. Only this thread pushes to it,
+    // and all joins of this thread have returned, hence no thread waits on these tasks. We drop them here.
+    while let Some(task) = self.worker.pop() {
+      drop(task);
+    }
+  }
+
+  fn record_panic(&self, payload: Box<dyn Any + Send>) {
+    let mut panic = self.panic.lock().unwrap();
+    if panic.is_none() {
+      *panic = Some(payload);
+    }
+    // Threads in the lookback of a chained scan may wait on a block of the task that panicked.
+    // The cancellation makes them stop waiting.
+    self.cancellation.cancel();
+    // We don't call self.finish(), as that would run the continuation of the task whose finish function is running.
+    self.is_finished.store(true, Ordering::Release);
+  }
+
+  fn has_panicked(&self) -> bool {
+    // The cancellation is checked first, such that we only lock the mutex after a panic or cancellation.
+    self.is_cancelled() && self.panic.lock().unwrap().is_some()
+  }
+
+  // Runs a and b, possibly in parallel, and returns both results.
+  // b is pushed on the deque of this thread, such that other threads can steal it, and a is run on this thread.
+  // While b is not finished, this thread helps with other tasks. If b was not stolen, that is b itself.
+  // This may be called from single tasks (Task::new_single) and from within other joins,
+  // but not from the body of a data-parallel loop.
+  pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+  where
+    A: FnOnce(&Workers) -> RA,
+    B: FnOnce(&Workers) -> RB + Send,
+    RB: Send
+  {
+    let slot = JoinSlot{ function: UnsafeCell::new(Some(b)), result: UnsafeCell::new(None), done: AtomicBool::new(false) };
+    self.push_task(Task::new_single::<JoinPointer<B, RB>>(run_join::<B, RB>, JoinPointer(&slot)));
+
+    // Even if a panics, we must wait for b, as b refers to 'slot' on our stack.
+    let result_a = catch_unwind(AssertUnwindSafe(|| a(self)));
+
+    while !slot.done.load(Ordering::Acquire) {
+      let result = catch_unwind(AssertUnwindSafe(|| {
+        if let Some(task) = self.claim_task(self.thread_index) {
+          self.start_task(task, self.thread_index);
+        } else if !self.is_cancelled() {
+          self.try_assist(self.thread_index);
+        }
+      }));
+      if let Err(payload) = result {
+        // Another task panicked. We cannot unwind yet, as b may still be running.
+        self.record_panic(payload);
+      }
+    }
+
+    let result_b = unsafe { (*slot.result.get()).take() }.unwrap();
+    match (result_a, result_b) {
+      (Ok(value_a), Ok(value_b)) => (value_a, value_b),
+      (Err(payload), _) | (_, Err(payload)) => resume_unwind(payload)
+    }
+  }
+
+  fn work_loop(&self, thread_index: usize) {
     loop {
-      if self.is_finished.load(Ordering::Relaxed) {
+      if self.is_finished.load(Ordering::Relaxed) || self.cancellation.is_cancelled() {
         return;
       }
 
@@ -81,13 +236,8 @@ impl<'a> Workers<'a> {
       return Some(item);
     }
     // If we didn't have tasks on our own deque, we try to steal a task from another thread.
-    let mut other_index = thread_index;
-    let increment = if thread_index % 2 == 0 { 1 } else { self.worker_count - 1 };
-    loop {
-      other_index = (other_index + increment) % self.worker_count;
-      if other_index == thread_index {
-        break;
-      }
+    // We first try threads close to us, as the data of their tasks is likely in a shared cache or on our NUMA node.
+    for &other_index in self.victims(thread_index) {
       if let Some(item) = self.stealers[other_index].steal().success() {
         return Some(item);
       }
@@ -95,16 +245,16 @@ impl<'a> Workers<'a> {
     None
   }
 
-  fn try_assist(&self, thread_index: usize) {
-    let mut other_index = thread_index;
-    let increment = if thread_index % 2 == 0 { 1 } else { self.worker_count - 1 };
-
-    loop {
-      other_index = (other_index + increment) % self.worker_count;
-      if other_index == thread_index {
-        break;
-      }
+  fn victims(&self, thread_index: usize) -> &[usize] {
+    let count = self.worker_count - 1;
+    &self.victims[thread_index * count .. (thread_index + 1) * count]
+  }
 
+  fn try_assist(&self, thread_index: usize) {
+    // We prefer to assist threads on our own NUMA node, as the memory of a task is likely close to the thread that started it.
+    // The blocks of most data-parallel tasks are claimed from a shared counter, hence we cannot choose which blocks we assist.
+    // Node-local tasks however divide their blocks over the nodes, and we then claim blocks of our own node first.
+    for &other_index in self.victims(thread_index) {
       let check = self.activities[other_index].load(Ordering::Relaxed);
       if check.ptr().is_null() { continue; }
 
@@ -129,14 +279,17 @@ impl<'a> Workers<'a> {
 
       let (current_index, count_claimed) = if task.work_two_sided {
         let i = task.work_index.load(Ordering::Relaxed);
-        (i, (i >> 16) + (i & 0xFFFF))
+        (i, two_sided_claimed(i))
+      } else if let Some(ranges) = &task.node_ranges {
+        let i = claim_node_local(ranges, self.node).unwrap_or(u64::MAX);
+        (i, i)
       } else {
         let i = task.work_index.fetch_add(1, Ordering::Relaxed);
         (i, i)
       };
 
       // Early out.
-      if count_claimed >= task.work_size {
+      if count_claimed >= task.work_size as u64 {
         signal.task_empty();
         self.end_task(task);
         break;
@@ -156,29 +309,42 @@ impl<'a> Workers<'a> {
       // Hence we can take unique ownership of this task here,
       // and pass it to finish.
       let task_ref: *const TaskObject<()> = &*task;
-      let finish = task.finish;
       // task.finish will drop the object. Hence we shouldn't do that here.
       std::mem::forget(task);
-      (finish)(self, task_ref as *mut TaskObject<()>);
+      self.call_finish(task_ref as *mut TaskObject<()>);
       return;
     }
 
     let task_ptr = task.into_raw();
     let task_ref = unsafe { &*task_ptr };
 
+    // The task is not visible to other threads yet, hence this finds a block.
+    // For other tasks, block 0 is reserved for this thread, as work_index starts at 1.
+    let first_index = match &task_ref.node_ranges {
+      Some(ranges) => claim_node_local(ranges, self.node).unwrap(),
+      None => 0
+    };
+
     // Since this thread previously had no activity (i.e., a null pointer),
     // we don't have to keep track of the reference count that was previously
     // stored in the AtomicTaggedPtr.
     self.activities[thread_index].store(TaggedPtr::new(task_ptr, 0), Ordering::Release);
 
     let signal = EmptySignal{ pointer: &self.activities[thread_index], task: task_ref, state: EmptySignalState::Main };
-    self.call_task(unsafe { &*task_ptr }, signal, 0);
+    self.call_task(unsafe { &*task_ptr }, signal, first_index);
   }
 
   // Calls the work function of a task, and calls end_task afterwards
-  fn call_task(&self, task: *const TaskObject<()>, signal: EmptySignal, first_index: u32) {
+  fn call_task(&self, task: *const TaskObject<()>, signal: EmptySignal, first_index: u64) {
     let task_ref = unsafe { &*task };
-    (task_ref.work.unwrap())(self, task, LoopArguments{ work_size: task_ref.work_size, work_index: &task_ref.work_index, empty_signal: signal, first_index });
+    // If the work function panics, we still call end_task, such that the task is deallocated by the last thread working on it.
+    // The signal is dropped while unwinding, which removes the task from activities.
+    let result = catch_unwind(AssertUnwindSafe(|| {
+      (task_ref.work.unwrap())(self, task, LoopArguments{ work_size: task_ref.work_size, work_index: &task_ref.work_index, empty_signal: signal, first_index, cancellation: self.cancellation, node_ranges: task_ref.node_ranges.as_deref(), node: self.node });
+    }));
+    if let Err(payload) = result {
+      self.record_panic(payload);
+    }
     self.end_task(task);
   }
 
@@ -193,11 +359,48 @@ impl<'a> Workers<'a> {
       // this task is not present anymore in activities at this point
       // and other threads are not working on this task any more.
       // Hence we can take unique ownership of this task now.
-      let finish = task_ref.finish;
-      // task.finish will drop the object. Hence we shouldn't do that here.
-      (finish)(self, task as *mut TaskObject<()>);
+      if self.has_panicked() {
+        // The finish function would continue the computation, or start its next phase.
+        // We only drop the task and its data.
+        (task_ref.deallocate)(task as *mut TaskObject<()>);
+      } else {
+        // task.finish will drop the object. Hence we shouldn't do that here.
+        self.call_finish(task as *mut TaskObject<()>);
+      }
     }
   }
+
+  fn call_finish(&self, task: *mut TaskObject<()>) {
+    let task_ref = unsafe { &*task };
+    let finish = task_ref.finish;
+    // Finish functions may be nested, for instance when a finish function calls join.
+    // Hence we restore the continuation of the outer task afterwards.
+    let outer = self.continuation.replace(task_ref.continuation);
+    (finish)(self, task);
+    self.continuation.set(outer);
+  }
+}
+
+// The second closure of Workers::join and its result.
+// This lives on the stack of the thread that called join, which waits until 'done' is set.
+struct JoinSlot<B, RB> {
+  function: UnsafeCell<Option<B>>,
+  result: UnsafeCell<Option<std::thread::Result<RB>>>,
+  done: AtomicBool
+}
+
+struct JoinPointer<B, RB>(*const JoinSlot<B, RB>);
+
+// The closure is run on at most one thread, and the result is only read after 'done' is set.
+unsafe impl<B: Send, RB: Send> Send for JoinPointer<B, RB> {}
+unsafe impl<B: Send, RB: Send> Sync for JoinPointer<B, RB> {}
+
+fn run_join<B: FnOnce(&Workers) -> RB, RB>(workers: &Workers, task: *mut TaskObject<JoinPointer<B, RB>>) {
+  let slot = unsafe { &*TaskObject::take_data(task).0 };
+  let function = unsafe { (*slot.function.get()).take() }.unwrap();
+  let result = catch_unwind(AssertUnwindSafe(|| function(workers)));
+  unsafe { *slot.result.get() = Some(result) };
+  slot.done.store(true, Ordering::Release);
 }
 
 pub struct EmptySignal<'a> {
@@ -254,3 +457,166 @@ impl<'a> EmptySignal<'a> {
     self.state = EmptySignalState::DidSignal;
   }
 }
+
+impl<'a> Drop for EmptySignal<'a> {
+  fn drop(&mut self) {
+    // If the work function of the task panicked, it did not signal that the task is empty.
+    // We do that while unwinding, such that the reference count of the task can become zero.
+    self.task_empty();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use core::sync::atomic::{Ordering, AtomicU8, AtomicU64, AtomicUsize};
+  use crate::cases::scan;
+  use crate::core::cancellation::{CancellationToken, Cancelled};
+  use crate::core::task::*;
+  use crate::core::worker::*;
+  use crate::core::workassisting_loop::*;
+  use crate::utils::testing::thread_counts;
+
+  #[test]
+  fn cancel_running_scan() {
+    let size = 1024 * 1024 * 16;
+    let input: Box<[AtomicU64]> = (0 .. size).map(|_| AtomicU64::new(1)).collect();
+    let output: Box<[AtomicU64]> = (0 .. size).map(|_| AtomicU64::new(0)).collect();
+    let temp = scan::chained::create_temp(size);
+
+    for thread_count in thread_counts() {
+      output[1].store(0, Ordering::Relaxed);
+      let token = CancellationToken::new();
+      let result = std::thread::scope(|s| {
+        s.spawn(|| {
+          // Cancel as soon as the scan has written its first block.
+          while output[1].load(Ordering::Relaxed) == 0 {
+            std::hint::spin_loop();
+          }
+          token.cancel();
+        });
+        Workers::run_cancellable(thread_count, scan::our_chained::init_single(&input, &temp, &output), &token)
+      });
+      // The output is undefined after a cancellation, hence we only check the result.
+      assert_eq!(result, Err(Cancelled));
+    }
+  }
+
+  #[test]
+  fn run_without_cancellation() {
+    let size = 100_000;
+    let input: Box<[AtomicU64]> = (0 .. size).map(|_| AtomicU64::new(1)).collect();
+    let output: Box<[AtomicU64]> = (0 .. size).map(|_| AtomicU64::new(0)).collect();
+    let temp = scan::chained::create_temp(size);
+
+    for thread_count in thread_counts() {
+      let token = CancellationToken::new();
+      let result = Workers::run_cancellable(thread_count, scan::our_chained::init_single(&input, &temp, &output), &token);
+      assert_eq!(result, Ok(()));
+      assert_eq!(output[size - 1].load(Ordering::Relaxed), size as u64);
+    }
+  }
+
+  // Counts the number of times that the data of a task is dropped.
+  struct DropCounter<'a>(&'a AtomicUsize);
+
+  impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+      self.0.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  #[test]
+  fn panic_in_dataparallel_task_drops_data() {
+    for thread_count in thread_counts() {
+      let drops = AtomicUsize::new(0);
+      let task = Task::new_dataparallel::<DropCounter>(panic_at_block, finish_unreachable, DropCounter(&drops), 1024, false);
+      assert!(Workers::try_run(thread_count, task).is_err());
+      assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+  }
+
+  #[test]
+  fn panic_drops_unstarted_tasks() {
+    for thread_count in thread_counts() {
+      let drops = AtomicUsize::new(0);
+      let task = Task::new_single::<&AtomicUsize>(push_and_panic, &drops);
+      assert!(Workers::try_run(thread_count, task).is_err());
+      // The pushed task is either run by another thread, or dropped when the Workers stop.
+      assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+  }
+
+  fn panic_at_block(_workers: &Workers, _task: *const TaskObject<DropCounter>, loop_arguments: LoopArguments) {
+    workassisting_loop!(loop_arguments, |block_index| {
+      if block_index == 100 {
+        panic!("Panic in block {}", block_index);
+      }
+    });
+  }
+
+  fn finish_unreachable(_workers: &Workers, _task: *mut TaskObject<DropCounter>) {
+    unreachable!("The finish function should not be called after a panic");
+  }
+
+  fn push_and_panic(workers: &Workers, task: *mut TaskObject<&AtomicUsize>) {
+    let drops = unsafe { TaskObject::take_data(task) };
+    workers.push_task(Task::new_single::<DropCounter>(drop_data, DropCounter(drops)));
+    panic!("Panic after pushing a task");
+  }
+
+  fn drop_data(_workers: &Workers, task: *mut TaskObject<DropCounter>) {
+    drop(unsafe { TaskObject::take_data(task) });
+  }
+
+  struct TwoSidedData {
+    // The number of times that each block was run.
+    runs: Box<[AtomicU8]>,
+    // The number of times that the distribution was concluded, and the sum of the sequential and parallel counts.
+    conclusions: AtomicUsize,
+    total: AtomicUsize
+  }
+
+  // Runs a two-sided task with more blocks than fit in 16 bits, to test the 32-bit halves of work_index
+  // and their decoding in try_assist.
+  #[test]
+  fn two_sided_blocks_run_once() {
+    let work_size = 1 << 20;
+    for thread_count in thread_counts() {
+      let data = TwoSidedData{
+        runs: (0 .. work_size).map(|_| AtomicU8::new(0)).collect(),
+        conclusions: AtomicUsize::new(0),
+        total: AtomicUsize::new(0)
+      };
+      Workers::run(thread_count, Task::new_dataparallel::<&TwoSidedData>(run_two_sided, finish_two_sided, &data, work_size as u32, true));
+      assert!(data.runs.iter().all(|runs| runs.load(Ordering::Relaxed) == 1));
+      assert_eq!(data.conclusions.load(Ordering::Relaxed), 1);
+      assert_eq!(data.total.load(Ordering::Relaxed), work_size);
+    }
+  }
+
+  #[test]
+  fn two_sided_claimed_counts_both_halves() {
+    assert_eq!(two_sided_claimed(0), 0);
+    assert_eq!(two_sided_claimed(70_000), 70_000);
+    assert_eq!(two_sided_claimed(70_000 << 32), 70_000);
+    assert_eq!(two_sided_claimed((3 << 32) | 100_000), 100_003);
+    assert_eq!(two_sided_claimed(((1 << 31) << 32) | (1 << 31)), 1 << 32);
+  }
+
+  fn run_two_sided(_workers: &Workers, task: *const TaskObject<&TwoSidedData>, loop_arguments: LoopArguments) {
+    let data = unsafe { TaskObject::get_data(task) };
+    workassisting_loop_two_sided!(loop_arguments, |block_index| {
+      data.runs[block_index as usize].fetch_add(1, Ordering::Relaxed);
+    }, |block_index| {
+      data.runs[block_index as usize].fetch_add(1, Ordering::Relaxed);
+    }, |sequential_count, parallel_count| {
+      data.conclusions.fetch_add(1, Ordering::Relaxed);
+      data.total.store(sequential_count as usize + parallel_count as usize, Ordering::Relaxed);
+    });
+  }
+
+  fn finish_two_sided(workers: &Workers, task: *mut TaskObject<&TwoSidedData>) {
+    let _ = unsafe { TaskObject::take_data(task) };
+    workers.finish();
+  }
+}
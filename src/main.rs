@@ -12,8 +12,21 @@ fn main() {
   }
 
   cases::scan::run(cpp_enabled);
+  cases::scan::run_streaming(cpp_enabled);
   cases::scan::run_inplace(cpp_enabled);
+  cases::scan::run_prefetch();
   cases::compact::run(cpp_enabled);
+  cases::compact::run_selectivity();
+  cases::compact::run_inplace();
+  cases::compact::run_records();
+  cases::compact::run_indices();
+  cases::partition::run();
+  cases::split::run();
+  cases::histogram::run();
+  cases::dedup::run();
+  cases::rle::run();
+  cases::sort::run(cpp_enabled);
+  cases::pipeline::run();
   
   // Not implemented, unsure if this is neccesary?
   // cases::scan_ratio::run(cpp_enabled, false);
@@ -1,4 +1,12 @@
 pub mod array;
 pub mod benchmark;
+pub mod epoch;
+pub mod lookback_wait;
+pub mod perf;
+pub mod prefetch;
 pub mod ptr;
+pub mod shared_slice;
+pub mod stores;
+#[cfg(test)]
+pub mod testing;
 pub mod global_constants;
@@ -1,7 +1,99 @@
-use core::sync::atomic::AtomicU64;
+use core::ops::Deref;
+use core::sync::atomic::{Ordering, AtomicU64};
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use crate::core::worker::*;
+use crate::core::task::*;
+use crate::core::workassisting_loop::*;
 
 pub unsafe fn alloc_undef_u64_array(length: usize) -> Box<[AtomicU64]> {
   let mut vector = Vec::with_capacity(length);
   vector.set_len(length);
   vector.into_boxed_slice()
 }
+
+// Views an array of atomics as an array of plain integers, for instance to pass the output of one task as input to the next.
+// The caller must assure that the array is not modified while the returned slice is alive.
+pub unsafe fn as_u64_slice(array: &[AtomicU64]) -> &[u64] {
+  core::slice::from_raw_parts(array.as_ptr() as *const u64, array.len())
+}
+
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+// An array that is aligned to, and padded to a multiple of, the huge page size.
+// On Linux we ask the kernel to back it with transparent huge pages, which reduces the number of TLB misses.
+// The memory is not touched here, such that the pages can be placed by first-touch; see fill_parallel.
+pub struct HugePageArray {
+  pointer: *mut AtomicU64,
+  length: usize
+}
+
+unsafe impl Send for HugePageArray {}
+unsafe impl Sync for HugePageArray {}
+
+pub unsafe fn alloc_undef_u64_array_huge(length: usize) -> HugePageArray {
+  let layout = huge_page_layout(length);
+  let pointer = alloc(layout);
+  if pointer.is_null() {
+    handle_alloc_error(layout);
+  }
+  #[cfg(target_os = "linux")]
+  {
+    // This is only a hint. If transparent huge pages are disabled, we silently continue with regular pages.
+    libc::madvise(pointer as *mut libc::c_void, layout.size(), libc::MADV_HUGEPAGE);
+  }
+  HugePageArray{ pointer: pointer as *mut AtomicU64, length }
+}
+
+fn huge_page_layout(length: usize) -> Layout {
+  let bytes = (length * core::mem::size_of::<AtomicU64>()).max(1).next_multiple_of(HUGE_PAGE_SIZE);
+  Layout::from_size_align(bytes, HUGE_PAGE_SIZE).unwrap()
+}
+
+impl Deref for HugePageArray {
+  type Target = [AtomicU64];
+
+  fn deref(&self) -> &[AtomicU64] {
+    unsafe { core::slice::from_raw_parts(self.pointer, self.length) }
+  }
+}
+
+impl Drop for HugePageArray {
+  fn drop(&mut self) {
+    unsafe { dealloc(self.pointer as *mut u8, huge_page_layout(self.length)) };
+  }
+}
+
+// Each block of fill_parallel spans one huge page, such that a page is only touched by one thread.
+const FILL_BLOCK_SIZE: usize = HUGE_PAGE_SIZE / core::mem::size_of::<AtomicU64>();
+
+// Initializes array[i] to value(i) on thread_count worker threads.
+// Linux allocates a page on the NUMA node of the thread that first writes to it.
+// Filling an array sequentially places all its pages on the node of the main thread,
+// whereas this distributes the pages over the nodes of the worker threads.
+// As this is a node-local task, the k-th part of the array is placed on the k-th node,
+// such that node-local tasks over the array mostly access memory on their own node.
+pub fn fill_parallel(thread_count: usize, array: &[AtomicU64], value: fn(u64) -> u64) {
+  let task = Task::new_dataparallel_node_local::<FillData>(fill_run, fill_finish, FillData{ array, value }, array.len().div_ceil(FILL_BLOCK_SIZE) as u32);
+  Workers::run(thread_count, task);
+}
+
+struct FillData<'a> {
+  array: &'a [AtomicU64],
+  value: fn(u64) -> u64
+}
+
+fn fill_run(_workers: &Workers, task: *const TaskObject<FillData>, loop_arguments: LoopArguments) {
+  let data = unsafe { TaskObject::get_data(task) };
+  workassisting_loop!(loop_arguments, |block_index| {
+    let start = block_index as usize * FILL_BLOCK_SIZE;
+    let end = (start + FILL_BLOCK_SIZE).min(data.array.len());
+    for i in start .. end {
+      data.array[i].store((data.value)(i as u64), Ordering::Relaxed);
+    }
+  });
+}
+
+fn fill_finish(workers: &Workers, task: *mut TaskObject<FillData>) {
+  let _ = unsafe { TaskObject::take_data(task) };
+  workers.finish();
+}
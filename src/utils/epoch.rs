@@ -0,0 +1,20 @@
+use core::sync::atomic::{Ordering, AtomicU64};
+
+// The state of a block in a chained scan can be tagged with the epoch of the scan that wrote it.
+// The lower STATE_FLAG_BITS bits contain the flag (e.g. STATE_PREFIX_AVAILABLE),
+// the remaining bits contain the epoch.
+// A state with a different epoch than the current scan is treated as STATE_INITIALIZED.
+// Hence a temp array can be reused without resetting it before every scan.
+pub const STATE_FLAG_BITS: u32 = 2;
+
+// Epoch 0 is never handed out, as newly created or reset temp arrays have epoch 0 in their state.
+static NEXT_EPOCH: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_epoch() -> u64 {
+  NEXT_EPOCH.fetch_add(1, Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn tagged_state(epoch: u64, flag: u64) -> u64 {
+  (epoch << STATE_FLAG_BITS) | flag
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 // Uncomment for the CPU you are using
 
 //// AMD Ryzen Threadripper 2950X
@@ -21,5 +23,14 @@ pub const MAX_SPEEDUP: u32 = 6;
 pub const COMP_MAX_THREADS: u32 = 32;
 pub const COMP_MAX_SPEEDUP: u32 = 6;
 
-
-
+// The time that the lookback of the chained scans waits on a block without state,
+// before it reduces that block itself (or yields, for in-place scans).
+// A block of 4096 elements is reduced in the order of a microsecond, hence under ordinary contention the thread that
+// claimed the previous block publishes its aggregate within a few microseconds. A thread that is descheduled is away
+// for a time slice of the OS scheduler, in the order of milliseconds. The timeout lies between these, such that we only
+// reduce or yield when the previous thread is likely descheduled. Counting iterations instead would depend on
+// the latency of the load of the state, and would expire within the time of one block when that load hits the cache.
+pub const LOOKBACK_TIMEOUT: Duration = Duration::from_micros(50);
+
+// Size of the last level cache in bytes, used to decide whether to use streaming stores.
+pub const LAST_LEVEL_CACHE_SIZE: usize = 32 * 1024 * 1024;
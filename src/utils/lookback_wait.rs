@@ -0,0 +1,42 @@
+use std::time::Instant;
+use crate::utils::global_constants::LOOKBACK_TIMEOUT;
+
+// The number of iterations between two reads of the clock. Most waits end within a few iterations,
+// hence those never read the clock.
+const CHECK_INTERVAL: u32 = 64;
+
+// Bounds the time that the lookback of a chained scan waits on a block without state. See LOOKBACK_TIMEOUT.
+pub struct LookbackWait {
+  spins: u32,
+  start: Option<Instant>
+}
+
+impl LookbackWait {
+  pub fn new() -> LookbackWait {
+    LookbackWait{ spins: 0, start: None }
+  }
+
+  // Starts a new wait, when the lookback proceeds to another block.
+  pub fn reset(&mut self) {
+    self.spins = 0;
+    self.start = None;
+  }
+
+  // Called in each iteration in which the block still has no state.
+  // Returns whether we waited at least LOOKBACK_TIMEOUT. Once that holds, it keeps holding until reset is called.
+  #[inline(always)]
+  pub fn expired(&mut self) -> bool {
+    self.spins += 1;
+    if !self.spins.is_multiple_of(CHECK_INTERVAL) {
+      return false;
+    }
+    let now = Instant::now();
+    match self.start {
+      None => {
+        self.start = Some(now);
+        false
+      },
+      Some(start) => now.duration_since(start) >= LOOKBACK_TIMEOUT
+    }
+  }
+}
@@ -0,0 +1,128 @@
+// Hardware counters for the cache references and cache misses of this process, via perf_event_open.
+// The counters are inherited by threads spawned after they are opened,
+// hence they include the threads of Workers::run.
+
+// Measures the hit rate of the last level cache while running f.
+// Returns None if the counters are not available, for instance when they are disallowed
+// by /proc/sys/kernel/perf_event_paranoid, or in a virtual machine without a virtual PMU.
+#[cfg(target_os = "linux")]
+pub fn measure_hit_rate<F: FnMut()>(mut f: F) -> Option<f32> {
+  let references = Counter::open(PERF_COUNT_HW_CACHE_REFERENCES)?;
+  let misses = Counter::open(PERF_COUNT_HW_CACHE_MISSES)?;
+  references.enable();
+  misses.enable();
+  f();
+  misses.disable();
+  references.disable();
+
+  let references = references.read()?;
+  let misses = misses.read()?;
+  if references == 0 {
+    return None;
+  }
+  Some(1.0 - misses as f32 / references as f32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn measure_hit_rate<F: FnMut()>(mut f: F) -> Option<f32> {
+  f();
+  None
+}
+
+#[cfg(target_os = "linux")]
+const PERF_TYPE_HARDWARE: u32 = 0;
+#[cfg(target_os = "linux")]
+const PERF_COUNT_HW_CACHE_REFERENCES: u64 = 2;
+#[cfg(target_os = "linux")]
+const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+
+#[cfg(target_os = "linux")]
+const FLAG_DISABLED: u64 = 1 << 0;
+#[cfg(target_os = "linux")]
+const FLAG_INHERIT: u64 = 1 << 1;
+#[cfg(target_os = "linux")]
+const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+#[cfg(target_os = "linux")]
+const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+#[cfg(target_os = "linux")]
+const PERF_EVENT_IOC_ENABLE: u64 = 0x2400;
+#[cfg(target_os = "linux")]
+const PERF_EVENT_IOC_DISABLE: u64 = 0x2401;
+
+// struct perf_event_attr from linux/perf_event.h (PERF_ATTR_SIZE_VER5).
+// The libc crate doesn't define it. The bitfields are combined in 'flags'.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+  kind: u32,
+  size: u32,
+  config: u64,
+  sample_period: u64,
+  sample_type: u64,
+  read_format: u64,
+  flags: u64,
+  wakeup_events: u32,
+  bp_type: u32,
+  config1: u64,
+  config2: u64,
+  branch_sample_type: u64,
+  sample_regs_user: u64,
+  sample_stack_user: u32,
+  clockid: i32,
+  sample_regs_intr: u64,
+  aux_watermark: u32,
+  sample_max_stack: u16,
+  reserved: u16
+}
+
+#[cfg(target_os = "linux")]
+struct Counter {
+  fd: libc::c_int
+}
+
+#[cfg(target_os = "linux")]
+impl Counter {
+  fn open(config: u64) -> Option<Counter> {
+    let attr = PerfEventAttr {
+      kind: PERF_TYPE_HARDWARE,
+      size: core::mem::size_of::<PerfEventAttr>() as u32,
+      config,
+      flags: FLAG_DISABLED | FLAG_INHERIT | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+      ..Default::default()
+    };
+    // Count this process (pid 0) and its future threads, on any cpu (-1), without a group leader (-1).
+    let fd = unsafe { libc::syscall(libc::SYS_perf_event_open, &attr as *const PerfEventAttr, 0, -1, -1, 0) };
+    if fd < 0 {
+      None
+    } else {
+      Some(Counter{ fd: fd as libc::c_int })
+    }
+  }
+
+  fn enable(&self) {
+    unsafe { libc::ioctl(self.fd, PERF_EVENT_IOC_ENABLE as _, 0) };
+  }
+
+  fn disable(&self) {
+    unsafe { libc::ioctl(self.fd, PERF_EVENT_IOC_DISABLE as _, 0) };
+  }
+
+  fn read(&self) -> Option<u64> {
+    let mut value: u64 = 0;
+    let bytes = unsafe { libc::read(self.fd, &mut value as *mut u64 as *mut libc::c_void, core::mem::size_of::<u64>()) };
+    if bytes == core::mem::size_of::<u64>() as isize {
+      Some(value)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Counter {
+  fn drop(&mut self) {
+    unsafe { libc::close(self.fd) };
+  }
+}
@@ -0,0 +1,33 @@
+// Software prefetch hints. A hint doesn't change the behaviour of the program,
+// and the functions are no-ops on architectures where we don't emit prefetch instructions.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Locality {
+  // Fetch into all cache levels, for data that is used soon.
+  L1,
+  // Fetch into the L2 cache and higher, for data that is used later.
+  L2
+}
+
+const CACHE_LINE_SIZE: usize = 64;
+
+// Issues a prefetch for every cache line of array.
+#[cfg(target_arch = "x86_64")]
+pub fn prefetch<T>(array: &[T], locality: Locality) {
+  use core::arch::x86_64::*;
+  let start = array.as_ptr() as *const i8;
+  let bytes = core::mem::size_of_val(array);
+  let mut offset = 0;
+  while offset < bytes {
+    unsafe {
+      match locality {
+        Locality::L1 => _mm_prefetch::<_MM_HINT_T0>(start.add(offset)),
+        Locality::L2 => _mm_prefetch::<_MM_HINT_T1>(start.add(offset))
+      }
+    }
+    offset += CACHE_LINE_SIZE;
+  }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn prefetch<T>(_array: &[T], _locality: Locality) {}
@@ -0,0 +1,37 @@
+use core::marker::PhantomData;
+
+// A mutable slice that can be shared between threads, where each index is written by at most one thread.
+// This is used for outputs of non-atomic element types, for instance in the generic compact.
+pub struct SharedSlice<'a, T> {
+  pointer: *mut T,
+  length: usize,
+  phantom: PhantomData<&'a mut [T]>
+}
+
+unsafe impl<T: Send> Send for SharedSlice<'_, T> {}
+unsafe impl<T: Send> Sync for SharedSlice<'_, T> {}
+
+impl<T> Clone for SharedSlice<'_, T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+impl<T> Copy for SharedSlice<'_, T> {}
+
+impl<'a, T> SharedSlice<'a, T> {
+  pub fn new(slice: &'a mut [T]) -> Self {
+    SharedSlice{ pointer: slice.as_mut_ptr(), length: slice.len(), phantom: PhantomData }
+  }
+
+  // Views the slice as a regular slice, for instance to use the output of one phase as input of the next.
+  // The caller must assure that no thread writes to the slice while the returned slice is in use.
+  pub unsafe fn as_slice(&self) -> &'a [T] {
+    core::slice::from_raw_parts(self.pointer, self.length)
+  }
+
+  // The caller must assure that no other thread reads or writes this index concurrently.
+  pub unsafe fn write(&self, index: usize, value: T) {
+    assert!(index < self.length);
+    *self.pointer.add(index) = value;
+  }
+}
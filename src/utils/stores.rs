@@ -0,0 +1,23 @@
+use crate::utils::global_constants::LAST_LEVEL_CACHE_SIZE;
+
+// Whether a kernel writes its output with regular stores or with non-temporal (streaming) stores.
+// Streaming stores bypass the caches. When the output is much larger than the last level cache,
+// this prevents the output from evicting the input, and saves reading each output cache line before it is written.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Stores {
+  Regular,
+  Streaming
+}
+
+impl Stores {
+  // Chooses streaming stores for out-of-place kernels with an output larger than twice the last level cache.
+  // In-place kernels use regular stores, as the cache lines of the output were just loaded when reading the input.
+  pub fn for_output<I, O>(input: &[I], output: &[O]) -> Stores {
+    let inplace = core::ptr::eq(input.as_ptr() as *const u8, output.as_ptr() as *const u8);
+    if !inplace && core::mem::size_of_val(output) > 2 * LAST_LEVEL_CACHE_SIZE {
+      Stores::Streaming
+    } else {
+      Stores::Regular
+    }
+  }
+}
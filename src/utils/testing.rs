@@ -0,0 +1,10 @@
+use crate::utils::global_constants::{AFFINITY_MAPPING, THREAD_COUNTS};
+
+// The thread counts to run the tests with: the entries of THREAD_COUNTS up to 8,
+// for which the cpus in AFFINITY_MAPPING exist on this machine, as Workers::run pins its threads to those cpus.
+pub fn thread_counts() -> Vec<usize> {
+  let cpus = std::thread::available_parallelism().map_or(1, |count| count.get());
+  THREAD_COUNTS.into_iter()
+    .filter(|&thread_count| thread_count <= 8 && AFFINITY_MAPPING[.. thread_count].iter().all(|&cpu| cpu < cpus))
+    .collect()
+}